@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id uuid NOT NULL,
+    subject TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    category TEXT NOT NULL,
+    published_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id)
+);
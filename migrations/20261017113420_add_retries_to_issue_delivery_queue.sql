@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Number of failed delivery attempts and earliest time for the next one
+ALTER TABLE issue_delivery_queue ADD COLUMN n_attempts SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
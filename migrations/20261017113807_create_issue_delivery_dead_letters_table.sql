@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Deliveries that permanently failed, kept around to be inspected and re-queued by an admin
+CREATE TABLE issue_delivery_dead_letters (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
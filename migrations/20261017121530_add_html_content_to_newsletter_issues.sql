@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Issues published before the html part existed only have a plain-text content
+ALTER TABLE newsletter_issues ADD COLUMN html_content TEXT NULL;
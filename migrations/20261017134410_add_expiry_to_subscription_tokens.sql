@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Confirmation tokens expire after `application.subscription_token_ttl_hours`
+-- and can only be used once
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at timestamptz NULL;
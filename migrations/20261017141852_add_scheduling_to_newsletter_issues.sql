@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Issues can be scheduled (`send_at`), they are only `published` once their deliveries are enqueued
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status DROP DEFAULT;
+    ALTER TABLE newsletter_issues ADD COLUMN send_at timestamptz NULL;
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Outcome of each email of an issue, kept after the delivery task leaves the queue
+CREATE TABLE deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('queued', 'sent', 'failed', 'bounced')),
+    -- Id of the message returned by the email provider once sent
+    provider_message_id TEXT NULL,
+    last_error TEXT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
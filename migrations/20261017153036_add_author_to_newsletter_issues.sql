@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Archive details: who wrote the issue and when
+BEGIN;
+    -- NULL for the issues published before the author was recorded
+    ALTER TABLE newsletter_issues ADD COLUMN author_id uuid NULL REFERENCES users (user_id);
+    ALTER TABLE newsletter_issues ADD COLUMN created_at timestamptz NULL;
+    UPDATE newsletter_issues
+        SET created_at = COALESCE(published_at, send_at, now())
+        WHERE created_at IS NULL;
+    ALTER TABLE newsletter_issues ALTER COLUMN created_at SET NOT NULL;
+COMMIT;
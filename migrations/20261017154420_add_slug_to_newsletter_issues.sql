@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Permalink of the issues in the public archive (`/issues/{slug}`)
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+    -- Same format as `IssueSlug`: the subject words followed by the beginning of the id
+    UPDATE newsletter_issues
+        SET slug = concat_ws(
+            '-',
+            NULLIF(trim(BOTH '-' FROM lower(regexp_replace(left(subject, 60), '[^a-zA-Z0-9]+', '-', 'g'))), ''),
+            left(replace(newsletter_issue_id::text, '-', ''), 8)
+        )
+        WHERE slug IS NULL;
+    ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+    ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
+COMMIT;
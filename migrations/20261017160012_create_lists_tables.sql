@@ -0,0 +1,27 @@
+-- Add migration script here
+-- Mailing lists: a subscriber may belong to several of them
+BEGIN;
+    CREATE TABLE lists (
+        list_id uuid PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        -- Used when no list is given when subscribing or publishing, it cannot be archived
+        is_default BOOLEAN NOT NULL DEFAULT false,
+        created_at timestamptz NOT NULL,
+        -- Archived lists are kept for the past issues, but cannot be subscribed to or published to
+        archived_at timestamptz NULL
+    );
+    CREATE UNIQUE INDEX lists_single_default ON lists (is_default) WHERE is_default;
+    CREATE TABLE list_memberships (
+        list_id uuid NOT NULL REFERENCES lists (list_id),
+        subscription_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        status TEXT NOT NULL CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed')),
+        created_at timestamptz NOT NULL,
+        PRIMARY KEY (list_id, subscription_id)
+    );
+    -- The single list that used to be implicit
+    INSERT INTO lists (list_id, name, is_default, created_at)
+        VALUES ('9b3e2f0a-6a52-4c1e-9d0b-3f7c1a2e5d48', 'Newsletter', true, now());
+    INSERT INTO list_memberships (list_id, subscription_id, status, created_at)
+        SELECT '9b3e2f0a-6a52-4c1e-9d0b-3f7c1a2e5d48', id, status, subscribed_at
+        FROM subscriptions;
+COMMIT;
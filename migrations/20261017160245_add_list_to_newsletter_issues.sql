@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Issues are sent to the confirmed members of a single list
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE newsletter_issues
+        SET list_id = (SELECT list_id FROM lists WHERE is_default)
+        WHERE list_id IS NULL;
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Preferences set by the subscribers themselves under `/subscriptions/preferences`
+BEGIN;
+    -- Nothing is sent to the subscriber until then
+    ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+    -- Categories of issues the subscriber does not want to receive,
+    -- categories of new issues are received by default
+    CREATE TABLE subscription_category_opt_outs (
+        subscription_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        category TEXT NOT NULL,
+        PRIMARY KEY (subscription_id, category)
+    );
+COMMIT;
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Saved audience filters, see `domain::segment_filter`
+BEGIN;
+    CREATE TABLE segments (
+        segment_id uuid PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        filter JSONB NOT NULL,
+        created_at timestamptz NOT NULL
+    );
+    -- The whole list when NULL
+    ALTER TABLE newsletter_issues ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Custom attributes (text, number or boolean values) and tags of the subscribers
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'::jsonb;
+    ALTER TABLE subscriptions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+    -- Tag filters of the segments
+    CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
+COMMIT;
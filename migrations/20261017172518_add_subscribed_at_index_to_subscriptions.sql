@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Keyset pagination of the subscribers under `/admin/subscribers`
+CREATE INDEX subscriptions_subscribed_at_idx ON subscriptions (subscribed_at, id);
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Actions taken by the admins on the subscribers (see `/admin/subscribers/{id}`)
+BEGIN;
+    CREATE TABLE subscriber_audit_log (
+        audit_id uuid PRIMARY KEY,
+        -- No foreign key: the entries outlive the subscribers deleted by the admins
+        subscriber_id uuid NOT NULL,
+        subscriber_email TEXT NOT NULL,
+        user_id uuid NOT NULL REFERENCES users (user_id),
+        action TEXT NOT NULL,
+        details TEXT NULL,
+        created_at timestamptz NOT NULL
+    );
+    CREATE INDEX subscriber_audit_log_subscriber_id_idx ON subscriber_audit_log (subscriber_id, created_at);
+COMMIT;
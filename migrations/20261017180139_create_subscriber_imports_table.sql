@@ -0,0 +1,15 @@
+-- Add migration script here
+-- CSV imports of subscribers (see `/admin/subscribers/import`), along with the outcome of each row
+CREATE TABLE subscriber_imports (
+    import_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    file_name TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    imported_count INT NOT NULL,
+    skipped_count INT NOT NULL,
+    rejected_count INT NOT NULL,
+    -- CSV file of the rows of the import, downloadable from the import page
+    results TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
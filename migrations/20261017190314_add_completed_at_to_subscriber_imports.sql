@@ -0,0 +1,4 @@
+-- Add migration script here
+-- NULL while the confirmation emails of the import are being sent
+ALTER TABLE subscriber_imports ADD COLUMN completed_at timestamptz NULL;
+UPDATE subscriber_imports SET completed_at = created_at;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- No bounces are reported by the email providers yet, a delivery is either queued, sent or failed
+ALTER TABLE deliveries DROP CONSTRAINT deliveries_status_check;
+ALTER TABLE deliveries ADD CONSTRAINT deliveries_status_check CHECK (status IN ('queued', 'sent', 'failed'));
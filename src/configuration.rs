@@ -5,6 +5,8 @@ use sqlx::ConnectOptions;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::email_client::{EmailClient, EmailProvider, MailtrapProvider, PostmarkProvider, SmtpProvider};
+use crate::issue_delivery_worker::RetryPolicy;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -18,11 +20,23 @@ pub struct Settings {
 /// Separate Configuration Settings Type for EmailClient (email_client.rs)
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    // Vendor used to deliver the emails, defaults to MailTrap
+    #[serde(default)]
+    pub provider: EmailProviderKind,
+    // API base url, or the connection url of the relay (e.g. `smtp://localhost:1025`) for SMTP
     pub base_url: String,
     pub sender_email: String,
     pub sender_name: String,
+    // API token for MailTrap and Postmark, password for SMTP
     pub authorization_token: Secret<String>,
-    pub timeout_milliseconds: u64
+    // Only used by SMTP, the relay is used without authentication when missing
+    pub smtp_username: Option<String>,
+    pub timeout_milliseconds: u64,
+    // Delivery attempts made by the `issue_delivery_worker` before giving up on an email,
+    // at most `i16::MAX`
+    pub max_attempts: u16,
+    // Base delay of the exponential backoff between two delivery attempts
+    pub retry_base_delay_milliseconds: u64
 }
 
 impl EmailClientSettings {
@@ -37,6 +51,56 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    /// The attempts are counted in a `SMALLINT` column of the delivery queue
+    pub fn retry_policy(&self) -> Result<RetryPolicy, String> {
+        if self.max_attempts > i16::MAX as u16 {
+            return Err(format!("max_attempts cannot be greater than {}.", i16::MAX));
+        }
+        Ok(RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: std::time::Duration::from_millis(self.retry_base_delay_milliseconds),
+        })
+    }
+
+    /// Build an `EmailClient` from the settings,
+    /// shared by the API server and the `issue_delivery_worker`
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid Sender Email Address");
+        let sender_name = self.sender_name().expect("Invalid Sender Name");
+        let timeout = self.timeout();
+        let provider: Box<dyn EmailProvider> = match self.provider {
+            EmailProviderKind::Mailtrap => Box::new(MailtrapProvider::new(
+                self.base_url,
+                self.authorization_token,
+                timeout
+            )),
+            EmailProviderKind::Postmark => Box::new(PostmarkProvider::new(
+                self.base_url,
+                self.authorization_token,
+                timeout
+            )),
+            EmailProviderKind::Smtp => Box::new(
+                SmtpProvider::new(
+                    &self.base_url,
+                    self.smtp_username,
+                    self.authorization_token,
+                    timeout
+                ).expect("Invalid SMTP connection url")
+            ),
+        };
+        EmailClient::new(provider, sender_email, sender_name)
+    }
+}
+
+/// Selects the `EmailProvider` implementation, e.g. `provider: postmark`
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProviderKind {
+    #[default]
+    Mailtrap,
+    Postmark,
+    Smtp,
 }
 
 /// Wrapper type for values that contains secrets, which attempts to limit
@@ -64,7 +128,26 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String, // the base url of the application server
-    pub hmac_secret: Secret<String>
+    pub hmac_secret: Secret<String>,
+    // Confirmation links older than this are rejected, and purged along with
+    // the subscriptions that were never confirmed
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: i64,
+    // Title and description of the RSS and Atom feeds of the published issues
+    pub feed_title: String,
+    pub feed_description: String,
+    // Attributes and tags the subscribe form may set through hidden fields,
+    // `attribute:<key>` and `tags` (comma separated)
+    #[serde(default)]
+    pub subscribe_form_attributes: Vec<String>,
+    #[serde(default)]
+    pub subscribe_form_tags: Vec<String>,
+}
+
+impl ApplicationSettings {
+    pub fn subscription_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_ttl_hours)
+    }
 }
 
 impl DatabaseSettings {
@@ -85,7 +168,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -0,0 +1,177 @@
+/// Content of an email: an optional HTML part along with the plain-text part
+/// that every client is able to render.
+///
+/// When only HTML is provided the plain-text part is derived from it.
+/// Markdown content is rendered to (sanitized) HTML first.
+#[derive(Debug)]
+pub struct EmailBody {
+    html: Option<String>,
+    text: String,
+}
+
+/// Column width of the plain-text part generated from HTML
+const TEXT_WIDTH: usize = 78;
+
+impl EmailBody {
+    pub fn parse(html: Option<String>, text: Option<String>) -> Result<Self, String> {
+        // Blank parts are treated as missing
+        let html = html.filter(|html| !html.trim().is_empty());
+        let text = text.filter(|text| !text.trim().is_empty());
+
+        match (html, text) {
+            (None, None) => Err("Either an html or a text content must be provided".into()),
+            (html, Some(text)) => Ok(Self { html, text }),
+            (Some(html), None) => {
+                let text = html_to_text(&html);
+                Ok(Self { html: Some(html), text })
+            }
+        }
+    }
+
+    /// Both parts are produced from the Markdown source: the rendered HTML is sanitized
+    /// (no scripts, styles or event handlers) and the text part derived from it
+    pub fn from_markdown(markdown: &str) -> Result<Self, String> {
+        if markdown.trim().is_empty() {
+            return Err("The markdown content must not be empty".into());
+        }
+        let mut html = String::new();
+        // No smart punctuation, the quotes of the merge tags fallbacks must stay as typed
+        let options = pulldown_cmark::Options::ENABLE_TABLES
+            | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+            | pulldown_cmark::Options::ENABLE_FOOTNOTES
+            | pulldown_cmark::Options::ENABLE_TASKLISTS;
+        let parser = pulldown_cmark::Parser::new_ext(markdown, options);
+        pulldown_cmark::html::push_html(&mut html, parser);
+        let html = restore_merge_tags_in_links(&ammonia::clean(&html));
+        let text = html_to_text(&html);
+        Ok(Self { html: Some(html), text })
+    }
+
+    pub fn html(&self) -> Option<&str> {
+        self.html.as_deref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Link targets are percent-encoded by the Markdown renderer, which turns
+/// `[Unsubscribe]({{unsubscribe_url}})` into `href="%7B%7Bunsubscribe_url%7D%7D"`.
+///
+/// The tags are restored, unless they hold characters that are not safe
+/// in an attribute value (the HTML is already sanitized at this point)
+fn restore_merge_tags_in_links(html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("%7B%7B") {
+        restored.push_str(&rest[..start]);
+        let encoded = match rest[start..].find("%7D%7D") {
+            Some(end) => &rest[start..start + end + 6],
+            None => break,
+        };
+        match urlencoding::decode(encoded) {
+            Ok(tag) if !tag.contains(['"', '\'', '<', '>', '&']) => restored.push_str(&tag),
+            _ => restored.push_str(encoded),
+        }
+        rest = &rest[start + encoded.len()..];
+    }
+    restored.push_str(rest);
+    restored
+}
+
+/// Plain-text fallback for an HTML part, links are kept as footnotes
+pub fn html_to_text(html: &str) -> String {
+    html2text::from_read(html.as_bytes(), TEXT_WIDTH)
+        .trim_end()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_none, assert_ok};
+    use crate::domain::email_body::EmailBody;
+
+    #[test]
+    fn test_a_body_without_any_part_is_rejected() {
+        assert_err!(EmailBody::parse(None, None));
+    }
+
+    #[test]
+    fn test_blank_parts_are_treated_as_missing() {
+        assert_err!(EmailBody::parse(Some("  ".into()), Some("\n".into())));
+    }
+
+    #[test]
+    fn test_a_text_only_body_has_no_html_part() {
+        let body = assert_ok!(EmailBody::parse(None, Some("Plain text".into())));
+        assert_none!(body.html());
+        assert_eq!(body.text(), "Plain text");
+    }
+
+    #[test]
+    fn test_a_provided_text_part_is_kept_as_is() {
+        let body = EmailBody::parse(
+            Some("<p>Rich text</p>".into()),
+            Some("Hand written text".into())
+        ).unwrap();
+        assert_eq!(body.html(), Some("<p>Rich text</p>"));
+        assert_eq!(body.text(), "Hand written text");
+    }
+
+    #[test]
+    fn test_the_text_part_is_generated_from_html_when_missing() {
+        let body = EmailBody::parse(
+            Some(r#"<h1>Title</h1><p>Read <a href="https://example.com/post">the post</a></p>"#.into()),
+            None
+        ).unwrap();
+        let text = body.text();
+        assert!(!text.contains('<'), "{} contains markup", text);
+        assert!(text.contains("Title"));
+        assert!(text.contains("the post"));
+        assert!(text.contains("https://example.com/post"));
+    }
+
+    #[test]
+    fn test_markdown_is_rendered_to_html() {
+        let body = EmailBody::from_markdown("# Title\n\nSome **bold** text").unwrap();
+        let html = body.html().unwrap();
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+    }
+
+    #[test]
+    fn test_the_text_part_is_derived_from_markdown() {
+        let body = EmailBody::from_markdown("# Title\n\nRead [the post](https://example.com/post)").unwrap();
+        let text = body.text();
+        assert!(!text.contains('<'), "{} contains markup", text);
+        assert!(text.contains("Title"));
+        assert!(text.contains("https://example.com/post"));
+    }
+
+    #[test]
+    fn test_html_embedded_in_markdown_is_sanitized() {
+        let body = EmailBody::from_markdown(
+            "Hello <script>alert(1)</script><img src=\"https://example.com/a.png\" onerror=\"alert(2)\">"
+        ).unwrap();
+        let html = body.html().unwrap();
+        assert!(!html.contains("script"), "{}", html);
+        assert!(!html.contains("onerror"), "{}", html);
+        assert!(html.contains("https://example.com/a.png"));
+    }
+
+    #[test]
+    fn test_merge_tags_survive_markdown_rendering() {
+        let body = EmailBody::from_markdown(
+            r#"Hi {{subscriber.name | default: "there"}}, [Unsubscribe]({{unsubscribe_url}})"#
+        ).unwrap();
+        let html = body.html().unwrap();
+        assert!(html.contains(r#"Hi {{subscriber.name | default: "there"}}"#), "{}", html);
+        assert!(html.contains(r#"href="{{unsubscribe_url}}""#), "{}", html);
+    }
+
+    #[test]
+    fn test_blank_markdown_is_rejected() {
+        assert_err!(EmailBody::from_markdown(" \n"));
+    }
+}
@@ -0,0 +1,71 @@
+use uuid::Uuid;
+
+/// URL-friendly identifier of a published issue, used by its public permalink
+/// (`/issues/{slug}`).
+///
+/// Built from the subject, followed by the beginning of the issue id so that
+/// two issues with the same subject get different slugs.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+/// Longest subject part of a slug, in characters
+const MAX_SUBJECT_LENGTH: usize = 60;
+
+impl IssueSlug {
+    pub fn new(subject: &str, newsletter_issue_id: Uuid) -> Self {
+        let mut slug = String::new();
+        for word in subject
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            if slug.len() + word.len() > MAX_SUBJECT_LENGTH {
+                break;
+            }
+            slug.push_str(&word.to_ascii_lowercase());
+            slug.push('-');
+        }
+        slug.push_str(&newsletter_issue_id.to_simple().to_string()[..8]);
+        Self(slug)
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use crate::domain::issue_slug::IssueSlug;
+
+    fn issue_id() -> Uuid {
+        Uuid::parse_str("1a2b3c4d-0000-0000-0000-000000000000").unwrap()
+    }
+
+    #[test]
+    fn test_the_subject_is_lowercased_and_hyphenated() {
+        let slug = IssueSlug::new("Issue #42: What's new?", issue_id());
+        assert_eq!(slug.as_ref(), "issue-42-what-s-new-1a2b3c4d");
+    }
+
+    #[test]
+    fn test_non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::new("Café ☕ time", issue_id());
+        assert_eq!(slug.as_ref(), "caf-time-1a2b3c4d");
+    }
+
+    #[test]
+    fn test_a_subject_without_any_word_keeps_the_id() {
+        let slug = IssueSlug::new("!!!", issue_id());
+        assert_eq!(slug.as_ref(), "1a2b3c4d");
+    }
+
+    #[test]
+    fn test_long_subjects_are_truncated_on_a_word_boundary() {
+        let slug = IssueSlug::new(&"word ".repeat(50), issue_id());
+        assert!(slug.as_ref().len() <= 60 + 8);
+        assert!(slug.as_ref().ends_with("word-1a2b3c4d"));
+    }
+}
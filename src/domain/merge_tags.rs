@@ -0,0 +1,233 @@
+//! Per-recipient merge tags of the newsletter issues, e.g. `Hi {{subscriber.name}}!`.
+//!
+//! A tag may carry a fallback, used when the value is missing or empty:
+//! `{{subscriber.name | default: "there"}}`.
+//!
+//! The custom attributes of the subscribers are available as `{{subscriber.attributes.<key>}}`.
+use std::borrow::Cow;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+
+/// Tags known to `render_merge_tags`, along with the attribute tags,
+/// anything else is rejected by `validate_merge_tags`
+const KNOWN_TAGS: [&str; 4] = [
+    "subscriber.name",
+    "subscriber.email",
+    "unsubscribe_url",
+    "preferences_url",
+];
+
+/// Prefix of the attribute tags, followed by the attribute key
+const ATTRIBUTE_TAG_PREFIX: &str = "subscriber.attributes.";
+
+/// Values of the tags for one recipient, `None` when there is no such value
+/// (e.g. on the public web version of an issue)
+#[derive(Default)]
+pub struct MergeValues<'a> {
+    pub subscriber_name: Option<&'a str>,
+    pub subscriber_email: Option<&'a str>,
+    pub unsubscribe_url: Option<&'a str>,
+    pub preferences_url: Option<&'a str>,
+    pub subscriber_attributes: Option<&'a SubscriberAttributes>,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, tag: &str) -> Option<Cow<'_, str>> {
+        match tag {
+            "subscriber.name" => self.subscriber_name.map(Cow::Borrowed),
+            "subscriber.email" => self.subscriber_email.map(Cow::Borrowed),
+            "unsubscribe_url" => self.unsubscribe_url.map(Cow::Borrowed),
+            "preferences_url" => self.preferences_url.map(Cow::Borrowed),
+            _ => {
+                let key = tag.strip_prefix(ATTRIBUTE_TAG_PREFIX)?;
+                self.subscriber_attributes?.text(key).map(Cow::Owned)
+            },
+        }
+    }
+}
+
+/// How the values are written in the content
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeFormat {
+    // The values are escaped
+    Html,
+    Text,
+}
+
+enum Segment<'c> {
+    Text(&'c str),
+    Tag {
+        name: &'c str,
+        fallback: Option<&'c str>,
+    },
+}
+
+/// Rejects the unknown tags, and the malformed ones
+pub fn validate_merge_tags(content: &str) -> Result<(), String> {
+    parse(content).map(|_| ())
+}
+
+/// Replace the tags with the recipient's values.
+///
+/// Content that does not validate is returned as is, it was stored before the tags were checked
+pub fn render_merge_tags(content: &str, values: &MergeValues, format: MergeFormat) -> String {
+    let segments = match parse(content) {
+        Ok(segments) => segments,
+        Err(_) => return content.to_owned(),
+    };
+    let mut rendered = String::with_capacity(content.len());
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Tag { name, fallback } => {
+                let value = values
+                    .get(name)
+                    .filter(|value| !value.trim().is_empty())
+                    .or_else(|| fallback.map(Cow::Borrowed))
+                    .unwrap_or_default();
+                match format {
+                    MergeFormat::Html => rendered.push_str(&htmlescape::encode_minimal(&value)),
+                    MergeFormat::Text => rendered.push_str(&value),
+                }
+            }
+        }
+    }
+    rendered
+}
+
+fn parse(content: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("A merge tag is not closed: {}", &rest[start..]))?;
+        let tag = &rest[start..start + end + 2];
+        segments.push(parse_tag(tag)?);
+        rest = &rest[start + end + 2..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+/// `tag` includes the braces
+fn parse_tag(tag: &str) -> Result<Segment<'_>, String> {
+    let inner = &tag[2..tag.len() - 2];
+    let (name, fallback) = match inner.split_once('|') {
+        Some((name, filter)) => (name.trim(), Some(parse_fallback(filter.trim(), tag)?)),
+        None => (inner.trim(), None),
+    };
+    let is_attribute = name
+        .strip_prefix(ATTRIBUTE_TAG_PREFIX)
+        .map(|key| SubscriberAttributes::parse_key(key).is_ok())
+        .unwrap_or(false);
+    if !KNOWN_TAGS.contains(&name) && !is_attribute {
+        return Err(format!(
+            "{} is not a known merge tag, use one of {} or {{{{{}<key>}}}}",
+            tag,
+            KNOWN_TAGS.map(|t| format!("{{{{{}}}}}", t)).join(", "),
+            ATTRIBUTE_TAG_PREFIX
+        ));
+    }
+    Ok(Segment::Tag { name, fallback })
+}
+
+/// `default: "value"`
+fn parse_fallback<'c>(filter: &'c str, tag: &str) -> Result<&'c str, String> {
+    filter
+        .strip_prefix("default:")
+        .map(str::trim)
+        .and_then(|value| value.strip_prefix('"'))
+        .and_then(|value| value.strip_suffix('"'))
+        .filter(|value| !value.contains('"'))
+        .ok_or_else(|| format!(r#"{} has an invalid fallback, expected `default: "value"`"#, tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use crate::domain::merge_tags::{render_merge_tags, validate_merge_tags, MergeFormat, MergeValues};
+    use crate::domain::subscriber_attributes::SubscriberAttributes;
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            subscriber_name: Some("Tom & Jerry"),
+            subscriber_email: Some("tom@example.com"),
+            unsubscribe_url: Some("https://example.com/unsubscribe?token=abc"),
+            preferences_url: Some("https://example.com/preferences?token=abc"),
+            subscriber_attributes: None,
+        }
+    }
+
+    #[test]
+    fn test_known_tags_are_replaced() {
+        let rendered = render_merge_tags(
+            "Hi {{subscriber.name}} ({{ subscriber.email }}), {{unsubscribe_url}}",
+            &values(),
+            MergeFormat::Text
+        );
+        assert_eq!(rendered, "Hi Tom & Jerry (tom@example.com), https://example.com/unsubscribe?token=abc");
+    }
+
+    #[test]
+    fn test_values_are_escaped_in_html() {
+        let rendered = render_merge_tags("<p>Hi {{subscriber.name}}</p>", &values(), MergeFormat::Html);
+        assert_eq!(rendered, "<p>Hi Tom &amp; Jerry</p>");
+    }
+
+    #[test]
+    fn test_the_fallback_is_used_for_missing_values() {
+        let content = r#"Hi {{subscriber.name | default: "there"}}!"#;
+        let rendered = render_merge_tags(content, &MergeValues::default(), MergeFormat::Text);
+        assert_eq!(rendered, "Hi there!");
+        let rendered = render_merge_tags(content, &values(), MergeFormat::Text);
+        assert_eq!(rendered, "Hi Tom & Jerry!");
+    }
+
+    #[test]
+    fn test_missing_values_without_fallback_are_left_empty() {
+        let rendered = render_merge_tags("Hi {{subscriber.name}}!", &MergeValues::default(), MergeFormat::Text);
+        assert_eq!(rendered, "Hi !");
+    }
+
+    #[test]
+    fn test_content_without_tags_is_valid() {
+        assert_ok!(validate_merge_tags("Plain content, with a } brace"));
+    }
+
+    #[test]
+    fn test_unknown_tags_are_rejected() {
+        assert_err!(validate_merge_tags("Hi {{subscriber.nickname}}"));
+    }
+
+    #[test]
+    fn test_attribute_tags_are_replaced() {
+        let attributes = serde_json::json!({"company": "Acme", "seats": 12});
+        let attributes = SubscriberAttributes::parse(attributes.as_object().unwrap().clone()).unwrap();
+        let values = MergeValues {
+            subscriber_attributes: Some(&attributes),
+            ..values()
+        };
+        let content = r#"{{subscriber.attributes.company}}: {{subscriber.attributes.seats}} seats, {{subscriber.attributes.plan | default: "free"}} plan"#;
+        assert_ok!(validate_merge_tags(content));
+        assert_eq!(render_merge_tags(content, &values, MergeFormat::Text), "Acme: 12 seats, free plan");
+        assert_err!(validate_merge_tags("{{subscriber.attributes.Company}}"));
+    }
+
+    #[test]
+    fn test_unclosed_tags_are_rejected() {
+        assert_err!(validate_merge_tags("Hi {{subscriber.name"));
+    }
+
+    #[test]
+    fn test_invalid_fallbacks_are_rejected() {
+        assert_err!(validate_merge_tags("Hi {{subscriber.name | there}}"));
+        assert_err!(validate_merge_tags(r#"Hi {{subscriber.name | default: there}}"#));
+    }
+
+    #[test]
+    fn test_invalid_content_is_rendered_as_is() {
+        let content = "Hi {{subscriber.nickname}}";
+        assert_eq!(render_merge_tags(content, &values(), MergeFormat::Text), content);
+    }
+}
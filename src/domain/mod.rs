@@ -1,4 +1,12 @@
 pub mod subscriber_name;
 pub mod subscriber_email;
 pub mod new_subscriber;
-
+pub mod email_body;
+pub mod issue_slug;
+pub mod merge_tags;
+pub mod list_name;
+pub mod segment_filter;
+pub mod segment_name;
+pub mod subscriber_tag;
+pub mod subscriber_attributes;
+pub mod subscriber_import;
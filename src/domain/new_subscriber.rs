@@ -1,7 +1,11 @@
+use crate::domain::subscriber_attributes::SubscriberAttributes;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_tag::SubscriberTag;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: SubscriberAttributes,
+    pub tags: Vec<SubscriberTag>,
 }
@@ -0,0 +1,263 @@
+//! Saved audience filters of the segments, stored as JSON and compiled to a SQL condition
+//! over the `subscriptions` table (aliased as `s`), e.g.
+//!
+//! ```json
+//! {"type": "all", "filters": [
+//!     {"type": "subscribed_within_days", "days": 30},
+//!     {"type": "email_domain", "domain": "edu"}
+//! ]}
+//! ```
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::Postgres;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_tag::SubscriberTag;
+
+/// Statuses of `subscriptions`
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+/// Upper bound of the number of conditions in a filter, groups included
+const MAX_CONDITIONS: usize = 50;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SegmentFilter {
+    /// Every filter matches
+    All { filters: Vec<SegmentFilter> },
+    /// At least one of the filters matches
+    Any { filters: Vec<SegmentFilter> },
+    Not { filter: Box<SegmentFilter> },
+    SubscribedWithinDays { days: i32 },
+    SubscribedBefore { date: DateTime<Utc> },
+    SubscribedAfter { date: DateTime<Utc> },
+    /// The domain of the address or one of its parents, `edu` matches `mit.edu`
+    EmailDomain { domain: String },
+    Status { status: String },
+    Tag { tag: String },
+    /// The attribute is set to `value`, numbers and booleans compared as written, e.g. `"12"`
+    Attribute { key: String, value: String },
+}
+
+/// Value bound to one of the placeholders of a compiled filter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SqlValue {
+    Text(String),
+    Int(i32),
+    Timestamp(DateTime<Utc>),
+}
+
+impl SegmentFilter {
+    /// Parse and validate a filter written by an admin
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let filter: Self = serde_json::from_str(json)
+            .map_err(|e| format!("The filter is not valid: {}", e))?;
+        filter.validate()?;
+        Ok(filter)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.n_conditions() > MAX_CONDITIONS {
+            return Err(format!("A filter cannot have more than {} conditions.", MAX_CONDITIONS));
+        }
+        self.validate_condition()
+    }
+
+    fn validate_condition(&self) -> Result<(), String> {
+        match self {
+            Self::All { filters } | Self::Any { filters } => {
+                if filters.is_empty() {
+                    return Err("A group of filters cannot be empty.".into());
+                }
+                filters.iter().try_for_each(Self::validate_condition)
+            },
+            Self::Not { filter } => filter.validate_condition(),
+            Self::SubscribedWithinDays { days } if !(1..=36500).contains(days) => {
+                Err(format!("{} is not a valid number of days.", days))
+            },
+            Self::EmailDomain { domain } => normalize_domain(domain).map(|_| ()),
+            Self::Tag { tag } => SubscriberTag::parse(tag).map(|_| ()),
+            Self::Attribute { key, .. } => SubscriberAttributes::parse_key(key).map(|_| ()),
+            Self::Status { status } if !STATUSES.contains(&status.as_str()) => {
+                Err(format!("{} is not a valid status, use one of {}.", status, STATUSES.join(", ")))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn n_conditions(&self) -> usize {
+        match self {
+            Self::All { filters } | Self::Any { filters } => {
+                1 + filters.iter().map(Self::n_conditions).sum::<usize>()
+            },
+            Self::Not { filter } => 1 + filter.n_conditions(),
+            _ => 1,
+        }
+    }
+
+    /// The SQL condition and the values of its placeholders,
+    /// numbered from `$first_placeholder` on.
+    ///
+    /// The filter must have been validated
+    pub fn to_sql(&self, first_placeholder: usize) -> (String, Vec<SqlValue>) {
+        let mut values = Vec::new();
+        let sql = self.write_sql(first_placeholder, &mut values);
+        (sql, values)
+    }
+
+    fn write_sql(&self, first_placeholder: usize, values: &mut Vec<SqlValue>) -> String {
+        let mut placeholder = |value: SqlValue| {
+            values.push(value);
+            format!("${}", first_placeholder + values.len() - 1)
+        };
+        match self {
+            Self::All { filters } | Self::Any { filters } => {
+                let operator = if matches!(self, Self::All { .. }) { " AND " } else { " OR " };
+                let conditions: Vec<_> = filters
+                    .iter()
+                    .map(|f| f.write_sql(first_placeholder, values))
+                    .collect();
+                format!("({})", conditions.join(operator))
+            },
+            Self::Not { filter } => format!("NOT {}", filter.write_sql(first_placeholder, values)),
+            Self::SubscribedWithinDays { days } => format!(
+                "(s.subscribed_at >= now() - make_interval(days => {}))",
+                placeholder(SqlValue::Int(*days))
+            ),
+            Self::SubscribedBefore { date } => {
+                format!("(s.subscribed_at < {})", placeholder(SqlValue::Timestamp(*date)))
+            },
+            Self::SubscribedAfter { date } => {
+                format!("(s.subscribed_at >= {})", placeholder(SqlValue::Timestamp(*date)))
+            },
+            Self::EmailDomain { domain } => {
+                // Validated: letters, digits, dots and hyphens only, nothing `LIKE` would interpret
+                let domain = normalize_domain(domain).unwrap_or_default();
+                let domain = placeholder(SqlValue::Text(domain));
+                format!(
+                    "(lower(split_part(s.email, '@', 2)) = {domain} \
+                    OR lower(split_part(s.email, '@', 2)) LIKE '%.' || {domain})",
+                    domain = domain
+                )
+            },
+            Self::Status { status } => {
+                format!("(s.status = {})", placeholder(SqlValue::Text(status.clone())))
+            },
+            Self::Tag { tag } => {
+                let tag = SubscriberTag::parse(tag).map(|t| t.as_ref().to_owned()).unwrap_or_default();
+                format!("({} = ANY(s.tags))", placeholder(SqlValue::Text(tag)))
+            },
+            Self::Attribute { key, value } => {
+                let key = placeholder(SqlValue::Text(key.clone()));
+                format!("(s.attributes ->> {} = {})", key, placeholder(SqlValue::Text(value.clone())))
+            },
+        }
+    }
+}
+
+/// Lowercase, without the leading dot of e.g. `.edu`
+fn normalize_domain(domain: &str) -> Result<String, String> {
+    let normalized = domain.trim().trim_start_matches('.').to_lowercase();
+    let is_valid = !normalized.is_empty()
+        && normalized.len() <= 253
+        && normalized
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if is_valid {
+        Ok(normalized)
+    } else {
+        Err(format!("{} is not a valid email domain.", domain))
+    }
+}
+
+/// Bind the values of a compiled filter, in order
+pub fn bind_sql_values<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    values: &'q [SqlValue],
+) -> Query<'q, Postgres, PgArguments> {
+    for value in values {
+        query = match value {
+            SqlValue::Text(text) => query.bind(text),
+            SqlValue::Int(int) => query.bind(int),
+            SqlValue::Timestamp(timestamp) => query.bind(timestamp),
+        };
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use crate::domain::segment_filter::{SegmentFilter, SqlValue};
+
+    #[test]
+    fn test_filters_compile_to_numbered_placeholders() {
+        let filter = SegmentFilter::parse(r#"{"type": "all", "filters": [
+            {"type": "subscribed_within_days", "days": 30},
+            {"type": "not", "filter": {"type": "status", "status": "unsubscribed"}}
+        ]}"#).unwrap();
+
+        let (sql, values) = filter.to_sql(2);
+
+        assert_eq!(
+            sql,
+            "((s.subscribed_at >= now() - make_interval(days => $2)) AND NOT (s.status = $3))"
+        );
+        assert_eq!(values, vec![SqlValue::Int(30), SqlValue::Text("unsubscribed".into())]);
+    }
+
+    #[test]
+    fn test_email_domains_are_normalized() {
+        let filter = SegmentFilter::parse(r#"{"type": "email_domain", "domain": ".EDU"}"#).unwrap();
+
+        let (sql, values) = filter.to_sql(1);
+
+        assert!(sql.contains("= $1 OR"));
+        assert_eq!(values, vec![SqlValue::Text("edu".into())]);
+    }
+
+    #[test]
+    fn test_tags_and_attributes_are_compiled() {
+        let filter = SegmentFilter::parse(r#"{"type": "any", "filters": [
+            {"type": "tag", "tag": "Beta"},
+            {"type": "attribute", "key": "company", "value": "Acme"}
+        ]}"#).unwrap();
+
+        let (sql, values) = filter.to_sql(1);
+
+        assert_eq!(sql, "(($1 = ANY(s.tags)) OR (s.attributes ->> $2 = $3))");
+        assert_eq!(
+            values,
+            vec![
+                SqlValue::Text("beta".into()),
+                SqlValue::Text("company".into()),
+                SqlValue::Text("Acme".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_filters_are_rejected() {
+        for json in [
+            r#"{"type": "any", "filters": []}"#,
+            r#"{"type": "status", "status": "bounced"}"#,
+            r#"{"type": "email_domain", "domain": "%"}"#,
+            r#"{"type": "subscribed_within_days", "days": 0}"#,
+            r#"{"type": "subscribed_within_days", "days": 30, "unknown": 1}"#,
+            r#"{"type": "name", "name": "ursula"}"#,
+            r#"{"type": "tag", "tag": "beta tester"}"#,
+            r#"{"type": "attribute", "key": "Company", "value": "Acme"}"#,
+            "not json",
+        ] {
+            assert_err!(SegmentFilter::parse(json), "{} was accepted", json);
+        }
+    }
+
+    #[test]
+    fn test_filters_have_a_bounded_size() {
+        let status = r#"{"type": "status", "status": "confirmed"}"#;
+        let filters = |n: usize| vec![status; n].join(",");
+        assert_ok!(SegmentFilter::parse(&format!(r#"{{"type": "any", "filters": [{}]}}"#, filters(49))));
+        assert_err!(SegmentFilter::parse(&format!(r#"{{"type": "any", "filters": [{}]}}"#, filters(50))));
+    }
+}
@@ -0,0 +1,48 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Name of a segment, shown on the admin pages (segments, compose form)
+#[derive(Debug)]
+pub struct SegmentName(String);
+
+impl SegmentName {
+    /// Surrounding whitespace is trimmed
+    pub fn parse(name: String) -> Result<Self, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("The segment name is required.".into());
+        }
+        if name.graphemes(true).count() > 100 {
+            return Err("The segment name cannot be longer than 100 characters.".into());
+        }
+        Ok(Self(name.to_owned()))
+    }
+}
+
+impl AsRef<str> for SegmentName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::segment_name::SegmentName;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn test_whitespace_only_names_are_rejected() {
+        assert_err!(SegmentName::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn test_a_name_longer_than_100_graphemes_is_rejected() {
+        assert_ok!(SegmentName::parse("å".repeat(100)));
+        assert_err!(SegmentName::parse("å".repeat(101)));
+    }
+
+    #[test]
+    fn test_names_are_trimmed() {
+        let name = SegmentName::parse(" Students\n".to_string()).unwrap();
+        assert_eq!(name.as_ref(), "Students");
+    }
+}
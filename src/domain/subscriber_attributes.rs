@@ -0,0 +1,121 @@
+use serde_json::{Map, Value};
+
+/// Upper bound of the number of attributes of a subscriber
+const MAX_ATTRIBUTES: usize = 50;
+
+/// Longest text value of an attribute
+const MAX_VALUE_LENGTH: usize = 500;
+
+/// Custom key/value attributes of a subscriber (e.g. `{"company": "Acme", "seats": 12}`),
+/// used by the `{{subscriber.attributes.<key>}}` merge tags and the segments.
+///
+/// Values are text, numbers or booleans
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse(attributes: Map<String, Value>) -> Result<Self, String> {
+        if attributes.len() > MAX_ATTRIBUTES {
+            return Err(format!("A subscriber cannot have more than {} attributes.", MAX_ATTRIBUTES));
+        }
+        for (key, value) in &attributes {
+            Self::parse_key(key)?;
+            match value {
+                Value::String(text) if text.chars().count() > MAX_VALUE_LENGTH => {
+                    return Err(format!(
+                        "The value of {} cannot be longer than {} characters.",
+                        key,
+                        MAX_VALUE_LENGTH
+                    ));
+                },
+                Value::String(_) | Value::Number(_) | Value::Bool(_) => {},
+                _ => return Err(format!("The value of {} must be a text, a number or a boolean.", key)),
+            }
+        }
+        Ok(Self(attributes))
+    }
+
+    /// Attributes read back from `subscriptions.attributes`, validated when they were stored
+    pub fn from_stored(attributes: Value) -> Self {
+        match attributes {
+            Value::Object(attributes) => Self(attributes),
+            _ => Self::default(),
+        }
+    }
+
+    /// Lowercase letters, digits and `_`, up to 50 characters, so that it fits in a merge tag
+    pub fn parse_key(key: &str) -> Result<&str, String> {
+        let is_valid = !key.is_empty()
+            && key.len() <= 50
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if is_valid {
+            Ok(key)
+        } else {
+            Err(format!("{} is not a valid attribute name.", key))
+        }
+    }
+
+    /// The value as written by the merge tags, `None` when missing
+    pub fn text(&self, key: &str) -> Option<String> {
+        match self.0.get(key)? {
+            Value::String(text) => Some(text.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+impl AsRef<Map<String, Value>> for SubscriberAttributes {
+    fn as_ref(&self) -> &Map<String, Value> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use serde_json::{json, Map, Value};
+    use crate::domain::subscriber_attributes::SubscriberAttributes;
+
+    fn attributes(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_scalar_values_are_accepted() {
+        let parsed = SubscriberAttributes::parse(attributes(json!({
+            "company": "Acme",
+            "seats": 12,
+            "trial": true
+        })));
+        assert_ok!(&parsed);
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.text("company").as_deref(), Some("Acme"));
+        assert_eq!(parsed.text("seats").as_deref(), Some("12"));
+        assert_eq!(parsed.text("plan"), None);
+    }
+
+    #[test]
+    fn test_nested_and_null_values_are_rejected() {
+        for value in [json!({"company": {"name": "Acme"}}), json!({"tags": []}), json!({"company": null})] {
+            assert_err!(SubscriberAttributes::parse(attributes(value)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys_are_rejected() {
+        for key in ["", "Company", "company.name", "first name"] {
+            assert_err!(SubscriberAttributes::parse_key(key), "{} was accepted", key);
+        }
+    }
+
+    #[test]
+    fn test_long_values_are_rejected() {
+        assert_err!(SubscriberAttributes::parse(attributes(json!({"bio": "a".repeat(501)}))));
+    }
+}
@@ -0,0 +1,219 @@
+//! CSV files of subscribers imported by the admins, read as they are uploaded:
+//! a header row naming the `email` and `name` columns (in any order, other columns ignored),
+//! then one subscriber per row
+use csv_core::{ReadRecordResult, Reader};
+
+/// How the imported subscribers are confirmed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Confirmed right away, e.g. when they had confirmed their address in the previous tool
+    Confirmed,
+    /// Pending until they follow the link of the confirmation email sent to them
+    SendConfirmation,
+}
+
+impl ImportMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "confirmed" => Ok(Self::Confirmed),
+            "send_confirmation" => Ok(Self::SendConfirmation),
+            _ => Err(format!("{} is not a valid import mode.", mode)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::SendConfirmation => "send_confirmation",
+        }
+    }
+}
+
+/// Fields of a row, or why they could not be read
+pub type CsvRecord = Result<Vec<String>, String>;
+
+/// Parse a CSV file chunk by chunk, chunks may end in the middle of a row
+pub struct CsvRecordReader {
+    reader: Reader,
+    // Fields of the row being read, `ends` are the end of each field in `output`
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    output_length: usize,
+    ends_length: usize,
+}
+
+impl Default for CsvRecordReader {
+    fn default() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            ends: vec![0; 16],
+            output_length: 0,
+            ends_length: 0,
+        }
+    }
+}
+
+impl CsvRecordReader {
+    /// The rows completed by `input`, an empty `input` marks the end of the file
+    pub fn read(&mut self, mut input: &[u8]) -> Vec<CsvRecord> {
+        let mut records = Vec::new();
+        loop {
+            let (result, n_input, n_output, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_length..],
+                &mut self.ends[self.ends_length..],
+            );
+            input = &input[n_input..];
+            // The ends are positions in the whole row, the reader keeps track of the fields
+            // written by the previous calls
+            self.output_length += n_output;
+            self.ends_length += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    records.push(self.take_record());
+                },
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> CsvRecord {
+        let mut start = 0;
+        let fields = self.ends[..self.ends_length]
+            .iter()
+            .map(|&end| {
+                let field = String::from_utf8(self.output[start..end].to_vec());
+                start = end;
+                field.map_err(|_| "The row is not valid UTF-8.".to_owned())
+            })
+            .collect();
+        self.output_length = 0;
+        self.ends_length = 0;
+        fields
+    }
+}
+
+/// Positions of the columns read from the header row
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportColumns {
+    pub email: usize,
+    pub name: usize,
+}
+
+impl ImportColumns {
+    pub fn from_header(header: &[String]) -> Result<Self, String> {
+        let position = |column: &str| {
+            header
+                .iter()
+                .position(|field| field.trim().eq_ignore_ascii_case(column))
+                .ok_or_else(|| format!("The header row of the file has no {} column.", column))
+        };
+        Ok(Self {
+            email: position("email")?,
+            name: position("name")?,
+        })
+    }
+
+    /// The email and the name of a row, empty when the row is too short
+    pub fn select(&self, record: &[String]) -> (String, String) {
+        let field = |i: usize| record.get(i).map(|f| f.trim().to_owned()).unwrap_or_default();
+        (field(self.email), field(self.name))
+    }
+}
+
+/// A row of a CSV file, every field quoted
+pub fn csv_line(fields: &[&str]) -> String {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|field| format!("\"{}\"", field.replace('"', "\"\"")))
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+    use crate::domain::subscriber_import::{csv_line, CsvRecordReader, ImportColumns, ImportMode};
+
+    fn read_chunks(chunks: &[&[u8]]) -> Vec<Vec<String>> {
+        let mut reader = CsvRecordReader::default();
+        let mut records = Vec::new();
+        for chunk in chunks.iter().chain([&b""[..]].iter()) {
+            records.extend(reader.read(chunk).into_iter().map(Result::unwrap));
+        }
+        records
+    }
+
+    fn strings(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_rows_can_span_several_chunks() {
+        let records = read_chunks(&[b"email,na", b"me\r\nursula@exam", b"ple.com,\"Le Guin, Ursula\"\nle"]);
+
+        assert_eq!(
+            records,
+            vec![
+                strings(&["email", "name"]),
+                strings(&["ursula@example.com", "Le Guin, Ursula"]),
+                strings(&["le"])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_rows_and_blank_lines_are_read() {
+        let name = "a".repeat(5000);
+        let row = format!("{}\n\n", vec![name.as_str(); 40].join(","));
+
+        let records = read_chunks(&[row.as_bytes()]);
+
+        assert_eq!(records, vec![vec![name; 40]]);
+    }
+
+    #[test]
+    fn test_the_byte_order_mark_is_skipped() {
+        let records = read_chunks(&[b"\xef\xbb\xbfemail,name\n"]);
+
+        assert_eq!(records, vec![strings(&["email", "name"])]);
+    }
+
+    #[test]
+    fn test_rows_that_are_not_utf8_are_rejected() {
+        let mut reader = CsvRecordReader::default();
+
+        let records = reader.read(b"ursula@example.com,\xff\nle-guin@example.com,ursula\n");
+
+        assert_err!(&records[0]);
+        assert_ok_eq!(&records[1], &strings(&["le-guin@example.com", "ursula"]));
+    }
+
+    #[test]
+    fn test_the_columns_are_found_by_name() {
+        let columns = ImportColumns::from_header(&strings(&["Name", "company", " EMAIL "]));
+
+        assert_ok_eq!(&columns, &ImportColumns { email: 2, name: 0 });
+        let columns = columns.unwrap();
+        assert_eq!(
+            columns.select(&strings(&["ursula ", "acme"])),
+            ("".to_owned(), "ursula".to_owned())
+        );
+        assert_err!(ImportColumns::from_header(&strings(&["email", "first_name"])));
+    }
+
+    #[test]
+    fn test_import_modes_are_parsed() {
+        assert_ok_eq!(ImportMode::parse("confirmed"), ImportMode::Confirmed);
+        assert_ok_eq!(ImportMode::parse("send_confirmation"), ImportMode::SendConfirmation);
+        assert_err!(ImportMode::parse("pending"));
+    }
+
+    #[test]
+    fn test_csv_fields_are_quoted() {
+        assert_eq!(csv_line(&["1", "Le Guin, \"Ursula\""]), "\"1\",\"Le Guin, \"\"Ursula\"\"\"\r\n");
+    }
+}
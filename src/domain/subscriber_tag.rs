@@ -0,0 +1,64 @@
+/// Label put on subscribers, e.g. `beta-tester`, matched by the segments
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Trimmed and lowercased: letters, digits, `-` and `_`, up to 50 characters
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        let tag = tag.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= 50
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if is_valid {
+            Ok(Self(tag))
+        } else {
+            Err(format!("{} is not a valid tag.", tag))
+        }
+    }
+
+    /// Comma separated tags, e.g. the `tags` field of the forms
+    pub fn parse_list(tags: &str) -> Result<Vec<Self>, String> {
+        let mut tags = tags
+            .split(',')
+            .filter(|tag| !tag.trim().is_empty())
+            .map(Self::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+    use crate::domain::subscriber_tag::SubscriberTag;
+
+    #[test]
+    fn test_tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Beta-Tester ").unwrap();
+        assert_eq!(tag.as_ref(), "beta-tester");
+    }
+
+    #[test]
+    fn test_invalid_tags_are_rejected() {
+        for tag in ["", "beta tester", "béta", "%", &"a".repeat(51)] {
+            assert_err!(SubscriberTag::parse(tag), "{} was accepted", tag);
+        }
+    }
+
+    #[test]
+    fn test_lists_of_tags_are_deduplicated() {
+        let tags = SubscriberTag::parse_list("vip, beta,,VIP ").unwrap();
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["beta", "vip"]);
+    }
+}
@@ -0,0 +1,76 @@
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use crate::email_client::{EmailMessage, EmailProvider};
+use crate::email_request::{FromEmailRequest, SendEmailRequest, ToEmailRequest};
+
+/// Sends emails through the MailTrap sending API (`POST /api/send`)
+pub struct MailtrapProvider {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>
+}
+
+impl MailtrapProvider {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration
+    ) -> Self {
+        // Complete Client-wide timeout configuration
+        // As opposed to request only timeout
+        let http_client = Client::builder()
+            .timeout(timeout) // Set a timeout for the HTTP client
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for MailtrapProvider {
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<Option<String>, anyhow::Error> {
+        let url = format!("{}/api/send", self.base_url);
+
+        let request_body = SendEmailRequest {
+            from: FromEmailRequest::new(message.sender, message.sender_name),
+            to: vec![ToEmailRequest::new(message.recipient)],
+            subject: message.subject,
+            html: message.html,
+            text: message.text,
+            category: message.category,
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect()
+        };
+
+        let response = self.http_client
+            .post(&url)
+            .header("Authorization", self.authorization_token.expose_secret())
+            .json(&request_body)
+            // Uncomment the line below to timeout the request
+            // .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        // The email is sent at this point, a response we can't parse only loses the message id
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_ids.into_iter().next());
+        Ok(message_id)
+    }
+}
+
+/// Response body of the MailTrap `/api/send` endpoint, e.g.
+/// `{"success":true,"message_ids":["8b627ff0-52b2-11f0-0000-f1e8ba0efc25"]}`
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    message_ids: Vec<String>,
+}
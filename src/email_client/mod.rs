@@ -1,37 +1,54 @@
-use reqwest::Client;
-use secrecy::{ExposeSecret, Secret};
+mod mailtrap;
+mod postmark;
+mod smtp;
+
+pub use mailtrap::MailtrapProvider;
+pub use postmark::PostmarkProvider;
+pub use smtp::SmtpProvider;
+
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
-use crate::email_request::{FromEmailRequest, SendEmailRequest, ToEmailRequest};
+
+/// An email ready to be handed over to an `EmailProvider`
+pub struct EmailMessage<'mail> {
+    pub sender: &'mail SubscriberEmail,
+    pub sender_name: &'mail SubscriberName,
+    pub recipient: &'mail SubscriberEmail,
+    pub subject: &'mail str,
+    // Sent as a `multipart/alternative` email along with `text` when present
+    pub html: Option<&'mail str>,
+    pub text: &'mail str,
+    pub category: &'mail str,
+    // Extra headers (name, value) such as `List-Unsubscribe`
+    pub headers: Vec<(&'static str, String)>,
+}
+
+/// A vendor (or protocol) able to deliver emails.
+///
+/// Route code only deals with `EmailClient`, the provider is selected
+/// through the `provider` field of `EmailClientSettings`
+#[async_trait::async_trait]
+pub trait EmailProvider: Send + Sync {
+    /// Returns the id the provider assigned to the message, when it reports one
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<Option<String>, anyhow::Error>;
+}
 
 pub struct EmailClient {
-    http_client: Client,
-    base_url: String,
+    provider: Box<dyn EmailProvider>,
     sender: SubscriberEmail,
     sender_name: SubscriberName,
-    authorization_token: Secret<String>
 }
 
 impl EmailClient {
     pub fn new(
-        base_url: String,
+        provider: Box<dyn EmailProvider>,
         sender: SubscriberEmail,
         sender_name: SubscriberName,
-        authorization_token: Secret<String>,
-        timeout: std::time::Duration
     ) -> Self {
-        // Complete Client-wide timeout configuration
-        // As opposed to request only timeout
-        let http_client = Client::builder()
-            .timeout(timeout) // Set a timeout for the HTTP client
-            .build()
-            .unwrap();
         Self {
-            http_client,
-            base_url,
+            provider,
             sender,
             sender_name,
-            authorization_token
         }
     }
 
@@ -39,47 +56,48 @@ impl EmailClient {
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
-        // html_content: &str,  // No html content required in MailTrap email schema
+        html: Option<&str>,
         text: &str,
         category: &str
-    ) -> Result<(), reqwest::Error> {
-
-        // Converting the base_url type from String to reqwest::Url
-        // Enables us to use reqwest::url::join for better URL handling
-        let url = format!("{}/api/send", self.base_url);
-
-        // using `as_ref` function as it returns directly &str from the type
-        let from_email = SubscriberEmail::parse(self.sender.as_ref().to_owned()).expect("Send Attempt for anInvalid Email");
-        let from_name = SubscriberName::parse(self.sender_name.as_ref().to_owned()).expect("Send Attempt for an Invalid Name");
-        let from = FromEmailRequest::new(from_email, from_name);
-
-        let to_email = ToEmailRequest::new(recipient.as_ref()).expect("Send Attempt for an Invalid 'To' Email");
-
-        let to = vec![
-            to_email,
-        ];
-
-        let request_body = SendEmailRequest {
-            from,
-            to,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = EmailMessage {
+            sender: &self.sender,
+            sender_name: &self.sender_name,
+            recipient,
             subject,
+            html,
             text,
-            category
+            category,
+            headers: Vec::new(),
         };
+        self.provider.send(&message).await
+    }
 
-        let builder = self
-            .http_client
-            .post(&url)
-            .header("Authorization", self.authorization_token.expose_secret())
-            .json(&request_body)
-
-            // Uncomment the line below to timeout the request
-            // .timeout(std::time::Duration::from_secs(10))
-            .send()
-            .await?
-            .error_for_status()?;
-
-        Ok(())
+    /// Same as `send_email`, along with the `List-Unsubscribe` and `List-Unsubscribe-Post`
+    /// headers (RFC 8058) that let mail clients show a one-click unsubscribe button
+    pub async fn send_newsletter(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html: Option<&str>,
+        text: &str,
+        category: &str,
+        unsubscribe_url: &str
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = EmailMessage {
+            sender: &self.sender,
+            sender_name: &self.sender_name,
+            recipient,
+            subject,
+            html,
+            text,
+            category,
+            headers: vec![
+                ("List-Unsubscribe", format!("<{}>", unsubscribe_url)),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click".into()),
+            ],
+        };
+        self.provider.send(&message).await
     }
 }
 
@@ -95,7 +113,7 @@ mod tests {
     use wiremock::matchers::{any, header, header_exists, method, path};
     use crate::domain::subscriber_email::SubscriberEmail;
     use crate::domain::subscriber_name::SubscriberName;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, MailtrapProvider};
 
     struct SendEmailBodyMatcher;
 
@@ -113,6 +131,7 @@ mod tests {
                 body.get("from").is_some() &&
                     body.get("to").is_some() &&
                     body.get("subject").is_some() &&
+                    body.get("html").is_some() &&
                     body.get("text").is_some() &&
                     body.get("category").is_some()
             } else {
@@ -144,13 +163,16 @@ mod tests {
 
     /// Get a test instance of `EmailClient`.
     fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
+        let provider = MailtrapProvider::new(
             base_url,
-            email(),
-            sender_name(),
             Secret::new(Faker.fake()),
             // lesser amount for testing
             std::time::Duration::from_millis(200)
+        );
+        EmailClient::new(
+            Box::new(provider),
+            email(),
+            sender_name(),
         )
     }
 
@@ -172,13 +194,46 @@ mod tests {
 
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &category())
+            .send_email(&email(), &subject(), Some(&content()), &content(), &category())
             .await;
 
         // Assert that the request was sent successfully
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn test_send_newsletter_adds_the_list_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/api/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_newsletter(
+                &email(),
+                &subject(),
+                None,
+                &content(),
+                &category(),
+                "https://example.com/subscriptions/unsubscribe?unsubscribe_token=abc"
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["headers"]["List-Unsubscribe"],
+            "<https://example.com/subscriptions/unsubscribe?unsubscribe_token=abc>"
+        );
+        assert_eq!(body["headers"]["List-Unsubscribe-Post"], "List-Unsubscribe=One-Click");
+    }
+
     #[tokio::test]
     async fn test_send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -196,7 +251,7 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&subscriber_email, &subject, &content, &category)
+            .send_email(&subscriber_email, &subject, Some(&content), &content, &category)
             .await;
 
         assert_err!(outcome);
@@ -218,9 +273,9 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &category())
+            .send_email(&email(), &subject(), Some(&content()), &content(), &category())
             .await;
 
         assert_err!(outcome);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,233 @@
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use crate::email_client::{EmailMessage, EmailProvider};
+
+/// Sends emails through the Postmark API (`POST /email`)
+pub struct PostmarkProvider {
+    http_client: Client,
+    base_url: String,
+    server_token: Secret<String>
+}
+
+impl PostmarkProvider {
+    pub fn new(
+        base_url: String,
+        server_token: Secret<String>,
+        timeout: std::time::Duration
+    ) -> Self {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            base_url,
+            server_token
+        }
+    }
+}
+
+/// Postmark expects PascalCase field names, unlike MailTrap
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'mail> {
+    from: String,
+    to: &'mail str,
+    subject: &'mail str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'mail str>,
+    text_body: &'mail str,
+    // Postmark's equivalent of MailTrap's `category`
+    tag: &'mail str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'mail>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'mail> {
+    name: &'mail str,
+    value: &'mail str,
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for PostmarkProvider {
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<Option<String>, anyhow::Error> {
+        let url = format!("{}/email", self.base_url);
+
+        let request_body = SendEmailRequest {
+            from: format!(
+                "{} <{}>",
+                quoted_display_name(message.sender_name.as_ref()),
+                message.sender.as_ref()
+            ),
+            to: message.recipient.as_ref(),
+            subject: message.subject,
+            html_body: message.html,
+            text_body: message.text,
+            tag: message.category,
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+        };
+
+        let response = self.http_client
+            .post(&url)
+            .header("X-Postmark-Server-Token", self.server_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
+        Ok(message_id)
+    }
+}
+
+/// Display name of the `From` header, as an RFC 5322 quoted string
+fn quoted_display_name(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use fake::{Fake, Faker};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence, Word};
+    use fake::faker::name::en::FirstName;
+    use secrecy::Secret;
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::domain::subscriber_name::SubscriberName;
+    use crate::email_client::{EmailClient, PostmarkProvider};
+    use crate::email_client::postmark::quoted_display_name;
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some() &&
+                    body.get("To").is_some() &&
+                    body.get("Subject").is_some() &&
+                    body.get("HtmlBody").is_some() &&
+                    body.get("TextBody").is_some() &&
+                    body.get("Tag").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let provider = PostmarkProvider::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200)
+        );
+        EmailClient::new(
+            Box::new(provider),
+            email(),
+            SubscriberName::parse(FirstName().fake()).unwrap(),
+        )
+    }
+
+    async fn send_email(email_client: &EmailClient) -> Result<Option<String>, anyhow::Error> {
+        let subject: String = Sentence(1..2).fake();
+        let content: String = Paragraph(1..10).fake();
+        let category: String = Word().fake();
+        email_client
+            .send_email(&email(), &subject, Some(&content), &content, &category)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_send_email_fires_a_request_to_the_postmark_email_endpoint() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(send_email(&email_client).await);
+    }
+
+    #[tokio::test]
+    async fn test_send_newsletter_adds_the_list_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_newsletter(
+                &email(),
+                "Newsletter title",
+                None,
+                "Newsletter body",
+                "subscribers",
+                "https://example.com/unsubscribe"
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_quotes_and_backslashes_of_the_sender_name_are_escaped() {
+        assert_eq!(quoted_display_name("Newsletter"), r#""Newsletter""#);
+        assert_eq!(quoted_display_name(r#"The "Weekly" \ Digest"#), r#""The \"Weekly\" \\ Digest""#);
+    }
+
+    #[tokio::test]
+    async fn test_send_email_fails_if_postmark_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(send_email(&email_client).await);
+    }
+}
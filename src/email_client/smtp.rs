@@ -0,0 +1,230 @@
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use crate::email_client::{EmailMessage, EmailProvider};
+
+/// Sends emails to an SMTP relay.
+///
+/// The relay is configured through a connection url,
+/// e.g. `smtp://localhost:1025` or `smtps://smtp.example.com:465`
+pub struct SmtpProvider {
+    transport: AsyncSmtpTransport<Tokio1Executor>
+}
+
+impl SmtpProvider {
+    pub fn new(
+        connection_url: &str,
+        username: Option<String>,
+        password: Secret<String>,
+        timeout: std::time::Duration
+    ) -> Result<Self, anyhow::Error> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::from_url(connection_url)?
+            .timeout(Some(timeout));
+        if let Some(username) = username {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned()
+            ));
+        }
+        Ok(Self {
+            transport: builder.build()
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for SmtpProvider {
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<Option<String>, anyhow::Error> {
+        let from = Mailbox::new(
+            Some(message.sender_name.as_ref().to_owned()),
+            message.sender.as_ref().parse()?
+        );
+        // SMTP relays do not hand back an id, use our own `Message-ID` instead
+        let domain = message.sender.as_ref().rsplit('@').next().unwrap_or("localhost");
+        let message_id = format!("<{}@{}>", uuid::Uuid::new_v4(), domain);
+        let builder = Message::builder()
+            .message_id(Some(message_id.clone()))
+            .from(from)
+            .to(message.recipient.as_ref().parse()?)
+            .subject(message.subject);
+        let mut email = match message.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+                message.text.to_owned(),
+                html.to_owned()
+            ))?,
+            None => builder.singlepart(SinglePart::plain(message.text.to_owned()))?,
+        };
+        // Same purpose as MailTrap's `category`, for filtering on the relay side
+        email.headers_mut().insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("X-Category"),
+            message.category.to_owned()
+        ));
+        for (name, value) in &message.headers {
+            email.headers_mut().insert_raw(HeaderValue::new(
+                HeaderName::new_from_ascii_str(name),
+                value.to_owned()
+            ));
+        }
+
+        self.transport.send(email).await?;
+        Ok(Some(message_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use claim::{assert_err, assert_ok};
+    use fake::Fake;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::name::en::FirstName;
+    use secrecy::Secret;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::domain::subscriber_name::SubscriberName;
+    use crate::email_client::{EmailClient, SmtpProvider};
+
+    /// Minimal SMTP stand-in: accepts a single connection, answers every command
+    /// and records the message received after `DATA`
+    async fn smtp_stand_in(reject_recipients: bool) -> (u16, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(String::new()));
+        let data = received.clone();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP stand-in\r\n").await.unwrap();
+
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK: queued\r\n").await.unwrap();
+                    } else {
+                        let mut data = data.lock().unwrap();
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("RCPT") && reject_recipients {
+                    b"550 No such user here\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+
+        (port, received)
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let provider = SmtpProvider::new(
+            &format!("smtp://127.0.0.1:{}", port),
+            None,
+            Secret::new(String::new()),
+            std::time::Duration::from_secs(2)
+        ).unwrap();
+        EmailClient::new(
+            Box::new(provider),
+            SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            SubscriberName::parse(FirstName().fake()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_email_delivers_the_message_to_the_smtp_server() {
+        let (port, received) = smtp_stand_in(false).await;
+        let email_client = email_client(port);
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+
+        let outcome = email_client
+            .send_email(&recipient, "Newsletter title", None, "Newsletter body", "subscribers")
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        assert!(received.contains(&format!("To: {}", recipient)));
+        assert!(received.contains("Subject: Newsletter title"));
+        assert!(received.contains("X-Category: subscribers"));
+        assert!(received.contains("Newsletter body"));
+    }
+
+    #[tokio::test]
+    async fn test_send_email_sends_both_parts_as_multipart_alternative() {
+        let (port, received) = smtp_stand_in(false).await;
+        let email_client = email_client(port);
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+
+        let outcome = email_client
+            .send_email(
+                &recipient,
+                "Newsletter title",
+                Some("<p>Newsletter html</p>"),
+                "Newsletter text",
+                "subscribers"
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        assert!(received.contains("multipart/alternative"));
+        assert!(received.contains("Content-Type: text/plain"));
+        assert!(received.contains("Content-Type: text/html"));
+        assert!(received.contains("<p>Newsletter html</p>"));
+        assert!(received.contains("Newsletter text"));
+    }
+
+    #[tokio::test]
+    async fn test_send_newsletter_adds_the_list_unsubscribe_headers() {
+        let (port, received) = smtp_stand_in(false).await;
+        let email_client = email_client(port);
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+
+        let outcome = email_client
+            .send_newsletter(
+                &recipient,
+                "Newsletter title",
+                None,
+                "Newsletter body",
+                "subscribers",
+                "https://example.com/unsubscribe"
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        assert!(received.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(received.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+
+    #[tokio::test]
+    async fn test_send_email_fails_if_the_smtp_server_rejects_the_recipient() {
+        let (port, _) = smtp_stand_in(true).await;
+        let email_client = email_client(port);
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+
+        let outcome = email_client
+            .send_email(&recipient, "Newsletter title", None, "Newsletter body", "subscribers")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -1,44 +1,45 @@
+use std::collections::BTreeMap;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
 
 #[derive(serde::Serialize)]
-pub struct FromEmailRequest {
-    email: SubscriberEmail,
-    name: SubscriberName
+pub struct FromEmailRequest<'mail> {
+    email: &'mail SubscriberEmail,
+    name: &'mail SubscriberName
 }
 
 
-impl FromEmailRequest {
-    pub fn new(email: SubscriberEmail, name: SubscriberName) -> Self {
+impl<'mail> FromEmailRequest<'mail> {
+    pub fn new(email: &'mail SubscriberEmail, name: &'mail SubscriberName) -> Self {
         Self { email, name }
     }
 }
 
 #[derive(serde::Serialize)]
-pub struct ToEmailRequest {
-    email: SubscriberEmail,
+pub struct ToEmailRequest<'mail> {
+    email: &'mail SubscriberEmail,
 }
 
-impl ToEmailRequest {
-    pub fn new(email: &str) -> Result<Self, anyhow::Error> {
-        if let Ok(email) = SubscriberEmail::parse(String::from(email)) {
-            Ok(Self { email })
-        } else {
-            Err(anyhow::anyhow!("Invalid To Email address provided"))
-        }
+impl<'mail> ToEmailRequest<'mail> {
+    pub fn new(email: &'mail SubscriberEmail) -> Self {
+        Self { email }
     }
 }
 
+/// Request body of the MailTrap `/api/send` endpoint
+/// (see `PostmarkProvider` for the PascalCase schema of Postmark)
 #[derive(serde::Serialize)]
-// utilize if pascal case of the fields is required
-// may be required in case of postmark
-// #[serde(rename_all = "PascalCase")]
 pub struct SendEmailRequest<'mail> {
-    pub from: FromEmailRequest,
-    pub to: Vec<ToEmailRequest>,
+    pub from: FromEmailRequest<'mail>,
+    pub to: Vec<ToEmailRequest<'mail>>,
     // optimizing the struct by using &str instead of String
     // which requires a new memory allocation every time
     pub subject: &'mail str,
+    // MailTrap sends a multipart email when both `html` and `text` are set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<&'mail str>,
     pub text: &'mail str,
-    pub category: &'mail str
-}
\ No newline at end of file
+    pub category: &'mail str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<&'mail str, &'mail str>
+}
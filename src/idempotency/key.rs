@@ -0,0 +1,53 @@
+/// Client provided key used to de-duplicate retried requests
+/// (sent in the `Idempotency-Key` header)
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.is_empty() {
+            anyhow::bail!("The idempotency key cannot be empty");
+        }
+        // Keeping an upper bound on the key length, as the key is stored in the database
+        let max_length = 50;
+        if s.len() >= max_length {
+            anyhow::bail!("The idempotency key must be shorter than {max_length} characters");
+        }
+        Ok(Self(s))
+    }
+}
+
+impl From<IdempotencyKey> for String {
+    fn from(k: IdempotencyKey) -> Self {
+        k.0
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use crate::idempotency::IdempotencyKey;
+
+    #[test]
+    fn test_an_empty_key_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("".to_string()));
+    }
+
+    #[test]
+    fn test_a_key_longer_than_50_characters_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("a".repeat(50)));
+    }
+
+    #[test]
+    fn test_a_valid_key_is_accepted() {
+        assert_ok!(IdempotencyKey::try_from(uuid::Uuid::new_v4().to_string()));
+    }
+}
@@ -0,0 +1,158 @@
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use chrono::Utc;
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::idempotency::IdempotencyKey;
+
+/// Mirrors the `header_pair` composite type defined in the `idempotency` migration
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+// sqlx needs to be told the name of the array type for `header_pair[]`
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+/// What the request handler should do after trying to claim an idempotency key
+// The enum is consumed right after `try_processing` returns, no point in boxing the transaction
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+    /// The key is new: process the request and save the response using the transaction
+    StartProcessing(Transaction<'static, Postgres>),
+    /// The key has already been processed: replay the stored response
+    ReturnSavedResponse(HttpResponse),
+}
+
+/// Claim the idempotency key by inserting a placeholder row.
+///
+/// Concurrent requests carrying the same key block on the `INSERT` until the
+/// first transaction commits (or rolls back), so they never race: once unblocked
+/// they find the saved response and replay it.
+#[tracing::instrument(
+    name = "Try processing idempotency key",
+    skip(pool)
+)]
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let n_inserted_rows = sqlx::query!(
+        r#"
+            INSERT INTO idempotency (user_id, idempotency_key, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        Utc::now()
+    )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    if n_inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(transaction))
+    } else {
+        let saved_response = get_saved_response(pool, idempotency_key, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+        Ok(NextAction::ReturnSavedResponse(saved_response))
+    }
+}
+
+#[tracing::instrument(
+    name = "Get saved response",
+    skip(pool)
+)]
+pub async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+            SELECT
+                response_status_code as "response_status_code!",
+                response_headers as "response_headers!: Vec<HeaderPairRecord>",
+                response_body as "response_body!"
+            FROM idempotency
+            WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some(r) = saved_response {
+        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+        let mut response = HttpResponse::build(status_code);
+        for HeaderPairRecord { name, value } in r.response_headers {
+            response.append_header((name, value));
+        }
+        Ok(Some(response.body(r.response_body)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Store the response for the idempotency key and commit the transaction
+/// opened by `try_processing`
+#[tracing::instrument(
+    name = "Save response",
+    skip(transaction, http_response)
+)]
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    // `MessageBody::Error` is not `Send` + `Sync`, hence it does not fit `anyhow::Error`
+    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    // `query_unchecked!` as the macro cannot check the custom `header_pair[]` type
+    sqlx::query_unchecked!(
+        r#"
+            UPDATE idempotency
+            SET
+                response_status_code = $3,
+                response_headers = $4,
+                response_body = $5
+            WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    )
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    // Rebuild the response from the buffered body
+    let http_response = response_head.set_body(body).map_into_boxed_body();
+    Ok(http_response)
+}
@@ -0,0 +1,627 @@
+use std::time::Duration;
+use chrono::Utc;
+use rand::Rng;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+use crate::configuration::Settings;
+use crate::domain::merge_tags::{render_merge_tags, MergeFormat, MergeValues};
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::get_connection_pool;
+use crate::templates::Templates;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Upper bound for the delay between two delivery attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How failed deliveries are retried, built from `EmailClientSettings`
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u16,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter: the delay doubles after each failed attempt
+    /// (capped to `MAX_RETRY_DELAY`) and a random half of it is added on top of the other half,
+    /// so that the deliveries failing together are not all retried at the same instant
+    pub fn backoff(&self, n_attempts: u16) -> Duration {
+        let exponent = u32::from(n_attempts.saturating_sub(1)).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(MAX_RETRY_DELAY);
+        let half = delay / 2;
+        half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// Background worker draining the `issue_delivery_queue`,
+/// runs alongside the API server (see `main.rs`)
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let retry_policy = configuration
+        .email_client
+        .retry_policy()
+        .map_err(anyhow::Error::msg)?;
+    let email_client = configuration.email_client.client();
+    let templates = Templates::load()?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        templates,
+        retry_policy,
+        configuration.application.base_url
+    ).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    templates: Templates,
+    retry_policy: RetryPolicy,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&pool, &email_client, &templates, &retry_policy, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            },
+            Err(_) => {
+                // Back off for a bit on transient failures (e.g. database unavailable)
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            },
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Dequeue a single delivery task and try to send the email.
+///
+/// A failed delivery is re-scheduled according to the `RetryPolicy`,
+/// or moved to the dead letters once it runs out of attempts.
+/// Subscribers who unsubscribed (or left the issue's list, paused their subscription or opted out
+/// of the issue's category) after the issue was published are skipped.
+///
+/// `base_url` is the application url, used to build the unsubscribe links.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty,
+        n_attempts=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &Templates,
+    retry_policy: &RetryPolicy,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_task(pool).await?;
+    let (mut transaction, task) = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email))
+        .record("n_attempts", task.n_attempts);
+
+    let email = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
+            );
+            // Retrying will not fix the address, keep it around for the admin to look at
+            move_to_dead_letters(transaction, &task, task.n_attempts, &e).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    let recipient = match get_recipient(pool, task.newsletter_issue_id, &task.subscriber_email).await? {
+        Some(recipient) => recipient,
+        None => {
+            tracing::info!("Skipping a subscriber who no longer receives the issue");
+            forget_delivery(&mut transaction, &task).await?;
+            delete_task(transaction, &task).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    // Failing to render the issue is retried (and dead-lettered) like failing to send it,
+    // the task would otherwise be dequeued again and again
+    match send_issue(pool, email_client, templates, base_url, task.newsletter_issue_id, &email, &recipient)
+        .await
+    {
+        Ok(message_id) => {
+            record_delivery(
+                &mut transaction,
+                &task,
+                DeliveryStatus::Sent,
+                message_id.as_deref(),
+                None
+            ).await?;
+            delete_task(transaction, &task).await?
+        },
+        Err(e) => {
+            let n_attempts = task.n_attempts + 1;
+            if i32::from(n_attempts) >= i32::from(retry_policy.max_attempts) {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Giving up after {} attempts.",
+                    n_attempts
+                );
+                move_to_dead_letters(transaction, &task, n_attempts, &e.to_string()).await?;
+            } else {
+                let retry_in = retry_policy.backoff(n_attempts as u16);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Retrying in {:?}.",
+                    retry_in
+                );
+                record_delivery(
+                    &mut transaction,
+                    &task,
+                    DeliveryStatus::Queued,
+                    None,
+                    Some(&e.to_string())
+                ).await?;
+                schedule_retry(transaction, &task, n_attempts, retry_in).await?;
+            }
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Render the issue for the recipient and send it, returns the message id of the provider if any
+async fn send_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &Templates,
+    base_url: &str,
+    newsletter_issue_id: Uuid,
+    email: &SubscriberEmail,
+    recipient: &Recipient,
+) -> Result<Option<String>, anyhow::Error> {
+    let unsubscribe_url = format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        base_url,
+        recipient.unsubscribe_token
+    );
+    let preferences_url = format!(
+        "{}/subscriptions/preferences?token={}",
+        base_url,
+        recipient.unsubscribe_token
+    );
+
+    let issue = get_issue(pool, newsletter_issue_id).await?;
+    let web_version_url = format!("{}/issues/{}", base_url, issue.slug);
+    let (subject, html_content, text_content) = merge_issue(
+        &issue.subject,
+        issue.html_content.as_deref(),
+        &issue.text_content,
+        &MergeValues {
+            subscriber_name: Some(&recipient.name),
+            subscriber_email: Some(email.as_ref()),
+            unsubscribe_url: Some(&unsubscribe_url),
+            preferences_url: Some(&preferences_url),
+            subscriber_attributes: Some(&recipient.attributes),
+        }
+    );
+    let (html_content, text_content) = render_newsletter_email(
+        templates,
+        html_content.as_deref(),
+        &text_content,
+        &web_version_url,
+        Some(&SubscriberLinks {
+            unsubscribe: &unsubscribe_url,
+            preferences: &preferences_url,
+        })
+    )?;
+    email_client
+        .send_newsletter(
+            email,
+            &subject,
+            html_content.as_deref(),
+            &text_content,
+            &issue.category,
+            &unsubscribe_url
+        )
+        .await
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i16,
+}
+
+/// `FOR UPDATE SKIP LOCKED` lets several workers pull from the queue concurrently:
+/// the row stays locked by the returned transaction until the task is deleted or re-scheduled
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+            SELECT newsletter_issue_id, subscriber_email, n_attempts
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+        "#,
+    )
+        .fetch_optional(&mut transaction)
+        .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            DELETE FROM issue_delivery_queue
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    retry_in: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(retry_in)?;
+    sqlx::query!(
+        r#"
+            UPDATE issue_delivery_queue
+            SET
+                n_attempts = $3,
+                execute_after = $4
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        execute_after
+    )
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_to_dead_letters(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i16,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO issue_delivery_dead_letters (
+                newsletter_issue_id,
+                subscriber_email,
+                n_attempts,
+                last_error,
+                failed_at
+            )
+            VALUES ($1, $2, $3, $4, now())
+            ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+            SET
+                n_attempts = EXCLUDED.n_attempts,
+                last_error = EXCLUDED.last_error,
+                failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        last_error
+    )
+        .execute(&mut transaction)
+        .await?;
+    record_delivery(&mut transaction, task, DeliveryStatus::Failed, None, Some(last_error)).await?;
+    delete_task(transaction, task).await
+}
+
+/// State of a row of the `deliveries` table
+#[derive(Clone, Copy, Debug)]
+enum DeliveryStatus {
+    Queued,
+    Sent,
+    Failed,
+}
+
+impl DeliveryStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Upsert: tasks enqueued before the `deliveries` table existed have no row yet
+#[tracing::instrument(skip(transaction, task, message_id, last_error))]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    status: DeliveryStatus,
+    message_id: Option<&str>,
+    last_error: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO deliveries (
+                newsletter_issue_id,
+                subscriber_email,
+                status,
+                provider_message_id,
+                last_error,
+                updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, now())
+            ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+            SET
+                status = EXCLUDED.status,
+                provider_message_id = EXCLUDED.provider_message_id,
+                last_error = EXCLUDED.last_error,
+                updated_at = EXCLUDED.updated_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        status.as_str(),
+        message_id,
+        last_error
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// The subscriber left before the email was sent, it is not part of the issue report
+#[tracing::instrument(skip_all)]
+async fn forget_delivery(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            DELETE FROM deliveries
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Resolve the merge tags of the subject and of both parts of an issue for one recipient
+pub fn merge_issue(
+    subject: &str,
+    html_content: Option<&str>,
+    text_content: &str,
+    values: &MergeValues,
+) -> (String, Option<String>, String) {
+    (
+        render_merge_tags(subject, values, MergeFormat::Text),
+        html_content.map(|html| render_merge_tags(html, values, MergeFormat::Html)),
+        render_merge_tags(text_content, values, MergeFormat::Text),
+    )
+}
+
+struct Recipient {
+    name: String,
+    unsubscribe_token: String,
+    attributes: SubscriberAttributes,
+}
+
+/// `None` when the subscriber is not a confirmed member of the issue's list anymore,
+/// is paused or opted out of the issue's category, see `enqueue_delivery_tasks`
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query!(
+        r#"
+            SELECT s.name, s.unsubscribe_token, s.attributes
+            FROM subscriptions s
+            JOIN list_memberships m ON m.subscription_id = s.id
+            JOIN newsletter_issues i ON i.list_id = m.list_id
+            WHERE
+                s.email = $1 AND
+                s.status = 'confirmed' AND
+                m.status = 'confirmed' AND
+                i.newsletter_issue_id = $2 AND
+                (s.paused_until IS NULL OR s.paused_until <= now()) AND
+                NOT EXISTS (
+                    SELECT 1 FROM subscription_category_opt_outs o
+                    WHERE o.subscription_id = s.id AND o.category = i.category
+                )
+        "#,
+        subscriber_email,
+        newsletter_issue_id
+    )
+        .fetch_optional(pool)
+        .await?
+        .map(|r| Recipient {
+            name: r.name,
+            unsubscribe_token: r.unsubscribe_token,
+            attributes: SubscriberAttributes::from_stored(r.attributes),
+        });
+    Ok(recipient)
+}
+
+struct NewsletterIssue {
+    slug: String,
+    subject: String,
+    html_content: Option<String>,
+    text_content: String,
+    category: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+            SELECT slug, subject, html_content, text_content, category
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+        .fetch_one(pool)
+        .await?;
+    Ok(issue)
+}
+
+/// Links of the email footer, personal to each subscriber
+pub struct SubscriberLinks<'a> {
+    pub unsubscribe: &'a str,
+    pub preferences: &'a str,
+}
+
+/// Both parts of a newsletter email: the issue content, with a "View in browser" link
+/// to its public page and, for the subscribers, the preferences and unsubscribe links.
+///
+/// An issue without an HTML part is sent as a text-only email
+pub fn render_newsletter_email(
+    templates: &Templates,
+    html_content: Option<&str>,
+    text_content: &str,
+    web_version_link: &str,
+    subscriber_links: Option<&SubscriberLinks>,
+) -> Result<(Option<String>, String), anyhow::Error> {
+    let mut context = tera::Context::new();
+    context.insert("web_version_link", web_version_link);
+    context.insert("unsubscribe_link", &subscriber_links.map(|l| l.unsubscribe));
+    context.insert("preferences_link", &subscriber_links.map(|l| l.preferences));
+    let html = match html_content {
+        Some(html_content) => {
+            context.insert("content", html_content);
+            Some(templates.render("emails/newsletter.html", &context)?)
+        },
+        None => None,
+    };
+    context.insert("content", text_content);
+    let text = templates.render("emails/newsletter.txt", &context)?;
+    Ok((html, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::issue_delivery_worker::{render_newsletter_email, RetryPolicy, SubscriberLinks, MAX_RETRY_DELAY};
+    use crate::templates::Templates;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_within_the_jitter_bounds() {
+        let retry_policy = retry_policy();
+        for n_attempts in 1..=5u16 {
+            let delay = Duration::from_secs(10 * 2u64.pow(u32::from(n_attempts) - 1));
+            let backoff = retry_policy.backoff(n_attempts);
+            assert!(backoff >= delay / 2, "{:?} is shorter than {:?}", backoff, delay / 2);
+            assert!(backoff <= delay, "{:?} is longer than {:?}", backoff, delay);
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let backoff = retry_policy().backoff(u16::MAX);
+        assert!(backoff <= MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_the_links_are_added_to_both_parts() {
+        let (html, text) = render_newsletter_email(
+            &Templates::load().unwrap(),
+            Some("<p>Content</p>"),
+            "Content",
+            "https://example.com/issues/title-1a2b3c4d",
+            Some(&SubscriberLinks {
+                unsubscribe: "https://example.com/subscriptions/unsubscribe?unsubscribe_token=token",
+                preferences: "https://example.com/subscriptions/preferences?token=token",
+            })
+        ).unwrap();
+        let html = html.unwrap();
+        assert!(html.contains(r#"<a href="https://example.com/issues/title-1a2b3c4d">View in browser</a>"#));
+        assert!(html.contains("<p>Content</p>"));
+        assert!(html.contains(
+            r#"<a href="https://example.com/subscriptions/unsubscribe?unsubscribe_token=token">Unsubscribe</a>"#
+        ));
+        assert!(text.starts_with("View in browser: https://example.com/issues/title-1a2b3c4d\n\nContent\n"));
+        assert!(html.contains(
+            r#"<a href="https://example.com/subscriptions/preferences?token=token">Manage your preferences</a>"#
+        ));
+        assert!(text.contains("Unsubscribe: https://example.com/subscriptions/unsubscribe?unsubscribe_token=token"));
+        assert!(text.contains("Manage your preferences: https://example.com/subscriptions/preferences?token=token"));
+    }
+
+    #[test]
+    fn test_a_text_only_issue_stays_text_only() {
+        let (html, text) = render_newsletter_email(
+            &Templates::load().unwrap(),
+            None,
+            "Content",
+            "https://example.com/issues/title",
+            None
+        ).unwrap();
+        assert!(html.is_none());
+        assert!(!text.contains("Unsubscribe"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::configuration::Settings;
+use crate::routes::enqueue_delivery_tasks;
+use crate::startup::get_connection_pool;
+
+/// How often the scheduled issues are checked
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Background task enqueuing the deliveries of the scheduled issues once their `send_at`
+/// has passed, runs alongside the API server (see `main.rs`)
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        // Failures are logged by `enqueue_due_issues`, the next run will catch up
+        let _ = enqueue_due_issues(&pool).await;
+        tokio::time::sleep(SCHEDULER_INTERVAL).await;
+    }
+}
+
+/// Publish the scheduled issues that are due, returns how many were published.
+///
+/// Each issue is published in its own transaction: an issue failing to publish is logged
+/// and left scheduled for the next run, without holding back the other due issues
+#[tracing::instrument(skip_all, err)]
+pub async fn enqueue_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let due_issues = sqlx::query!(
+        r#"
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE status = 'scheduled' AND send_at <= now()
+            ORDER BY send_at
+        "#
+    )
+        .fetch_all(pool)
+        .await?;
+    let mut n_published = 0;
+    for issue in due_issues {
+        match publish_due_issue(pool, issue.newsletter_issue_id).await {
+            Ok(true) => n_published += 1,
+            Ok(false) => {},
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    newsletter_issue_id = %issue.newsletter_issue_id,
+                    "Failed to publish a scheduled issue, retrying on the next run."
+                );
+            }
+        }
+    }
+
+    if n_published > 0 {
+        tracing::info!("Published {} scheduled issues", n_published);
+    }
+    Ok(n_published)
+}
+
+/// Returns `false` when the issue is not due anymore (e.g. cancelled or published meanwhile).
+///
+/// `SKIP LOCKED` keeps an issue from being picked twice, while the row lock
+/// makes a concurrent cancellation wait for the outcome
+#[tracing::instrument(skip(pool))]
+async fn publish_due_issue(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let published = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET
+                status = 'published',
+                published_at = now()
+            WHERE newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                FROM newsletter_issues
+                WHERE newsletter_issue_id = $1 AND status = 'scheduled' AND send_at <= now()
+                FOR UPDATE
+                SKIP LOCKED
+            )
+        "#,
+        newsletter_issue_id
+    )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    if published == 0 {
+        return Ok(false);
+    }
+    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -7,4 +7,10 @@ pub mod email_request;
 pub mod email_client;
 pub mod authentication;
 pub mod session_state;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod subscription_cleanup_worker;
+pub mod templates;
+
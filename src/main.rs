@@ -1,27 +1,11 @@
-use std::net::TcpListener;
-use secrecy::ExposeSecret;
-use sqlx::Connection;
-use sqlx::postgres::PgPoolOptions;
-use tracing_subscriber::layer::SubscriberExt;
-use crate::configuration::get_configuration;
-use crate::email_client::EmailClient;
-use crate::startup::{run, Application};
-use crate::telemetry::{get_subscriber, init_subscriber};
-
-mod routes;
-mod configuration;
-mod startup;
-
-mod telemetry;
-
-mod domain;
-mod email_client;
-mod email_request;
-mod authentication;
-
-mod session_state;
-
-mod utils;
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
+use email_newsletter_rust::configuration::get_configuration;
+use email_newsletter_rust::issue_delivery_worker::run_worker_until_stopped;
+use email_newsletter_rust::issue_scheduler::run_scheduler_until_stopped;
+use email_newsletter_rust::startup::Application;
+use email_newsletter_rust::subscription_cleanup_worker::run_cleanup_until_stopped;
+use email_newsletter_rust::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -32,9 +16,48 @@ async fn main() -> anyhow::Result<()> {
 
     // Panic if we can't read the configuration file
     let configuration = get_configuration().expect("Failed to read configuration");
-    
+
     // Removed the boilerplate code for the `spawn_app` function
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;    
+    let application = Application::build(configuration.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    // The newsletter deliveries are processed in the background, next to the API server
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(configuration.clone()));
+    let cleanup_task = tokio::spawn(run_cleanup_until_stopped(configuration));
+
+    // Shut down as soon as one of the tasks exits
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = scheduler_task => report_exit("Issue scheduler", o),
+        o = cleanup_task => report_exit("Subscription cleanup", o),
+    };
     Ok(())
 }
+
+fn report_exit(
+    task_name: &str,
+    outcome: Result<Result<(), impl Debug + Display>, JoinError>
+) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        },
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        },
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
@@ -1,15 +1,17 @@
 use actix_web::{web, HttpResponse};
-use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::http::header::LOCATION;
 use sqlx::PgPool;
 use uuid::Uuid;
 use anyhow::Context;
 use crate::session_state::TypedSession;
-use crate::utils::e500;
+use crate::templates::Templates;
+use crate::utils::{e500, render_page};
 // required for get_username anyhow::Error handling
 
 pub async fn admin_dashboard(
     session: TypedSession,
-    pool: web::Data<PgPool>
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
         get_username(user_id, &pool).await.map_err(e500)?
@@ -18,33 +20,9 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
-    Ok(
-        HttpResponse::Ok()
-            .content_type(ContentType::html())
-            .body(format!(
-                r#"
-                <!DOCTYPE html>
-                <html lang="en">
-                <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-                </head>
-                <body>
-                <p>Welcome {username}!</p>
-                <p>Available actions:</p>
-                <ol>
-                <li><a href="/admin/password">Change password</a></li>
-                <li>
-                <form name="logoutForm" action="/admin/logout" method="post">
-                <input type="submit" value="Logout" />
-                </form>
-                </li>
-                </ol>
-                </body>
-                </html>
-                "#
-            ))
-    )
+    let mut context = tera::Context::new();
+    context.insert("username", &username);
+    render_page(&templates, "admin/dashboard.html", &context)
 }
 
 #[tracing::instrument(
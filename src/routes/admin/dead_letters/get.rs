@@ -0,0 +1,57 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+#[derive(serde::Serialize)]
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    subject: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+/// List the deliveries the `issue_delivery_worker` gave up on
+pub async fn dead_letters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letters = get_dead_letters(&pool).await.map_err(e500)?;
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("dead_letters", &dead_letters);
+    render_page(&templates, "admin/dead_letters.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get dead letters",
+    skip(pool)
+)]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+            SELECT
+                d.newsletter_issue_id,
+                i.subject,
+                d.subscriber_email,
+                d.n_attempts,
+                d.last_error,
+                d.failed_at
+            FROM issue_delivery_dead_letters d
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            ORDER BY d.failed_at DESC
+        "#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the dead letters")?;
+    Ok(dead_letters)
+}
@@ -0,0 +1,104 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RequeueFormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+/// Move a dead letter back to the `issue_delivery_queue`, with a fresh attempt counter
+#[tracing::instrument(
+    name = "Re-queue a failed delivery",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn requeue_dead_letter(
+    form: web::Form<RequeueFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let requeued = requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    if requeued {
+        FlashMessage::info(format!(
+            "The delivery to {} has been re-queued.",
+            form.subscriber_email
+        )).send();
+    } else {
+        FlashMessage::error("The failed delivery does not exist anymore.").send();
+    }
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Database Connection From the pool")?;
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM issue_delivery_dead_letters
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the dead letter")?
+        .rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+            INSERT INTO issue_delivery_queue (
+                newsletter_issue_id,
+                subscriber_email,
+                n_attempts,
+                execute_after
+            )
+            VALUES ($1, $2, 0, now())
+            ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to enqueue the delivery task")?;
+    sqlx::query!(
+        r#"
+            UPDATE deliveries
+            SET
+                status = 'queued',
+                updated_at = now()
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the delivery status")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to re-queue a delivery")?;
+    Ok(true)
+}
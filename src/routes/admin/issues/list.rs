@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+/// Number of issues listed per page of the archive
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveQuery {
+    // 1-based, the first page when missing
+    page: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    subject: String,
+    category: String,
+    status: String,
+    // `None` for the issues published before the author was recorded
+    author: Option<String>,
+    created_at: DateTime<Utc>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+/// Archive of every issue, most recent first
+pub async fn list_issues(
+    query: web::Query<ArchiveQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    // One more row than displayed, to know whether there is a next page
+    let mut issues = get_archived_issues(
+        &pool,
+        ISSUES_PER_PAGE + 1,
+        (page as i64 - 1) * ISSUES_PER_PAGE
+    )
+        .await
+        .map_err(e500)?;
+    let has_next_page = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("issues", &issues);
+    context.insert("page", &page);
+    context.insert("has_next_page", &has_next_page);
+    render_page(&templates, "admin/issues.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get archived issues",
+    skip(pool)
+)]
+async fn get_archived_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+            SELECT
+                newsletter_issues.newsletter_issue_id,
+                newsletter_issues.subject,
+                newsletter_issues.category,
+                newsletter_issues.status,
+                users.username AS "author?",
+                newsletter_issues.created_at,
+                newsletter_issues.published_at
+            FROM newsletter_issues
+            LEFT JOIN users ON users.user_id = newsletter_issues.author_id
+            ORDER BY newsletter_issues.created_at DESC, newsletter_issues.newsletter_issue_id
+            LIMIT $1
+            OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the newsletter issues")?;
+    Ok(issues)
+}
@@ -0,0 +1,7 @@
+mod list;
+mod preview;
+mod report;
+
+pub use list::*;
+pub use preview::*;
+pub use report::*;
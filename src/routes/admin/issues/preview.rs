@@ -0,0 +1,182 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::merge_tags::MergeValues;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{merge_issue, render_newsletter_email, SubscriberLinks};
+use crate::startup::ApplicationBaseUrl;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page, see_other};
+
+/// Most addresses a test email can be sent to at once
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+#[derive(serde::Serialize)]
+pub struct PreviewedIssue {
+    subject: String,
+    html_content: Option<String>,
+    text_content: String,
+    category: String,
+    status: String,
+    slug: String,
+}
+
+impl PreviewedIssue {
+    /// The subject and both parts of the email as the subscribers get them, but for the
+    /// unsubscribe and preferences links: the merge tags and the footer use placeholder links
+    /// and no name nor attributes, `recipient` being the test address if any
+    fn final_content(
+        &self,
+        templates: &Templates,
+        base_url: &str,
+        recipient: Option<&str>,
+    ) -> Result<(String, Option<String>, String), anyhow::Error> {
+        let unsubscribe_url = format!("{}/subscriptions/unsubscribe", base_url);
+        let preferences_url = format!("{}/subscriptions/preferences", base_url);
+        let (subject, html_content, text_content) = merge_issue(
+            &self.subject,
+            self.html_content.as_deref(),
+            &self.text_content,
+            &MergeValues {
+                subscriber_name: None,
+                subscriber_email: recipient,
+                unsubscribe_url: Some(&unsubscribe_url),
+                preferences_url: Some(&preferences_url),
+                subscriber_attributes: None,
+            }
+        );
+        let (html_content, text_content) = render_newsletter_email(
+            templates,
+            html_content.as_deref(),
+            &text_content,
+            &format!("{}/issues/{}", base_url, self.slug),
+            Some(&SubscriberLinks {
+                unsubscribe: &unsubscribe_url,
+                preferences: &preferences_url,
+            })
+        )?;
+        Ok((subject, html_content, text_content))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestSendFormData {
+    // Separated by commas or whitespace
+    recipients: String,
+}
+
+/// The issue as it is going to be sent, along with a form to send it to a few test addresses
+pub async fn issue_preview(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<Templates>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = match get_previewed_issue(&pool, newsletter_issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let (subject, html_part, text_part) = issue.final_content(&templates, &base_url.0, None).map_err(e500)?;
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("newsletter_issue_id", &newsletter_issue_id);
+    context.insert("issue", &issue);
+    context.insert("subject", &subject);
+    context.insert("html_part", &html_part);
+    context.insert("text_part", &text_part);
+    context.insert("max_test_recipients", &MAX_TEST_RECIPIENTS);
+    render_page(&templates, "admin/issue_preview.html", &context)
+}
+
+/// Send the issue to the addresses entered by the admin, and to them only:
+/// no delivery is enqueued and the subscribers are left untouched
+#[tracing::instrument(
+    name = "Send a test newsletter issue",
+    skip(form, pool, email_client, base_url, templates),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_test_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<TestSendFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<Templates>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let preview_page = format!("/admin/issues/{}/preview", newsletter_issue_id);
+    let issue = match get_previewed_issue(&pool, newsletter_issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let recipients = match parse_test_recipients(&form.recipients) {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preview_page));
+        }
+    };
+    for recipient in &recipients {
+        let (subject, html_content, text_content) = issue
+            .final_content(&templates, &base_url.0, Some(recipient.as_ref()))
+            .map_err(e500)?;
+        let subject = format!("[TEST] {}", subject);
+        if let Err(e) = email_client
+            .send_email(recipient, &subject, html_content.as_deref(), &text_content, &issue.category)
+            .await
+        {
+            tracing::warn!(error.cause_chain = ?e, "Failed to send a test email");
+            FlashMessage::error(format!("Failed to send the test email to {}.", recipient)).send();
+            return Ok(see_other(&preview_page));
+        }
+    }
+    FlashMessage::info(format!("A test email has been sent to {} address(es).", recipients.len())).send();
+    Ok(see_other(&preview_page))
+}
+
+fn parse_test_recipients(recipients: &str) -> Result<Vec<SubscriberEmail>, String> {
+    let recipients = recipients
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|r| !r.is_empty())
+        .map(|r| SubscriberEmail::parse(r.to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err("At least one address is required.".into());
+    }
+    if recipients.len() > MAX_TEST_RECIPIENTS {
+        return Err(format!("At most {} addresses are allowed.", MAX_TEST_RECIPIENTS));
+    }
+    Ok(recipients)
+}
+
+#[tracing::instrument(
+    name = "Get previewed issue",
+    skip(pool)
+)]
+async fn get_previewed_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<PreviewedIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        PreviewedIssue,
+        r#"
+            SELECT subject, html_content, text_content, category, status, slug
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the issue")?;
+    Ok(issue)
+}
@@ -0,0 +1,137 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, render_page};
+
+#[derive(serde::Serialize)]
+struct IssueSummary {
+    subject: String,
+    status: String,
+    author: Option<String>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct FailedDelivery {
+    subscriber_email: String,
+    status: String,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+/// States of the `deliveries` table, in the order they are reported
+const DELIVERY_STATES: [&str; 3] = ["queued", "sent", "failed"];
+
+/// Delivery report of an issue: how many emails are in each state, and which ones failed
+pub async fn issue_report(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = match get_issue_summary(&pool, newsletter_issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let counts = get_delivery_counts(&pool, newsletter_issue_id).await.map_err(e500)?;
+    let failures = get_failed_deliveries(&pool, newsletter_issue_id).await.map_err(e500)?;
+
+    let counts: Vec<_> = DELIVERY_STATES
+        .iter()
+        .map(|state| {
+            let count = counts
+                .iter()
+                .find(|(status, _)| status == state)
+                .map(|(_, count)| *count)
+                .unwrap_or(0);
+            serde_json::json!({ "state": state, "count": count })
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("newsletter_issue_id", &newsletter_issue_id);
+    context.insert("issue", &issue);
+    context.insert("counts", &counts);
+    context.insert("failures", &failures);
+    render_page(&templates, "admin/issue_report.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get issue summary",
+    skip(pool)
+)]
+async fn get_issue_summary(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueSummary>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+            SELECT
+                newsletter_issues.subject,
+                newsletter_issues.status,
+                users.username AS "author?",
+                newsletter_issues.published_at
+            FROM newsletter_issues
+            LEFT JOIN users ON users.user_id = newsletter_issues.author_id
+            WHERE newsletter_issues.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the issue")?;
+    Ok(issue)
+}
+
+#[tracing::instrument(
+    name = "Get delivery counts",
+    skip(pool)
+)]
+async fn get_delivery_counts(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<(String, i64)>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT status, COUNT(*) AS "count!"
+            FROM deliveries
+            WHERE newsletter_issue_id = $1
+            GROUP BY status
+        "#,
+        newsletter_issue_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to count the deliveries")?;
+    Ok(rows.into_iter().map(|r| (r.status, r.count)).collect())
+}
+
+#[tracing::instrument(
+    name = "Get failed deliveries",
+    skip(pool)
+)]
+async fn get_failed_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+            SELECT subscriber_email, status, last_error, updated_at
+            FROM deliveries
+            WHERE
+                newsletter_issue_id = $1 AND
+                status = 'failed'
+            ORDER BY updated_at DESC
+        "#,
+        newsletter_issue_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the failed deliveries")?;
+    Ok(failures)
+}
@@ -0,0 +1,88 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+#[derive(serde::Serialize)]
+struct ListSummary {
+    list_id: Uuid,
+    name: String,
+    is_default: bool,
+    created_at: DateTime<Utc>,
+    archived_at: Option<DateTime<Utc>>,
+    n_confirmed_members: i64,
+}
+
+/// A list issues can be published to, offered by the compose form
+#[derive(serde::Serialize)]
+pub struct ActiveList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// Every list, archived ones included, along with the forms to create, rename and archive them
+pub async fn lists_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("lists", &lists);
+    render_page(&templates, "admin/lists.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get lists",
+    skip(pool)
+)]
+async fn get_lists(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+            SELECT
+                l.list_id,
+                l.name,
+                l.is_default,
+                l.created_at,
+                l.archived_at,
+                COUNT(s.id) AS "n_confirmed_members!"
+            FROM lists l
+            LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.status = 'confirmed'
+            LEFT JOIN subscriptions s ON s.id = m.subscription_id AND s.status = 'confirmed'
+            GROUP BY l.list_id
+            ORDER BY l.archived_at IS NOT NULL, l.is_default DESC, l.name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the lists")?;
+    Ok(lists)
+}
+
+/// The lists that are not archived, the default one first
+#[tracing::instrument(
+    name = "Get active lists",
+    skip(pool)
+)]
+pub async fn get_active_lists(pool: &PgPool) -> Result<Vec<ActiveList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ActiveList,
+        r#"
+            SELECT list_id, name, is_default
+            FROM lists
+            WHERE archived_at IS NULL
+            ORDER BY is_default DESC, name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the active lists")?;
+    Ok(lists)
+}
@@ -0,0 +1,165 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::list_name::ListName;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Create a list",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_list(
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match ListName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    if insert_list(&pool, &name).await.map_err(e500)? {
+        FlashMessage::info(format!("The list {} has been created.", name.as_ref())).send();
+    } else {
+        FlashMessage::error(format!("A list named {} already exists.", name.as_ref())).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+/// The members of the list and its issues are left untouched
+#[tracing::instrument(
+    name = "Rename a list",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn rename_list(
+    list_id: web::Path<Uuid>,
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match ListName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    match update_list_name(&pool, *list_id, &name).await.map_err(e500)? {
+        RenameOutcome::Renamed => {
+            FlashMessage::info(format!("The list has been renamed to {}.", name.as_ref())).send()
+        },
+        RenameOutcome::NameTaken => {
+            FlashMessage::error(format!("A list named {} already exists.", name.as_ref())).send()
+        },
+        RenameOutcome::NotFound => return Ok(HttpResponse::NotFound().finish()),
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+/// Archived lists cannot be subscribed to or published to anymore,
+/// their memberships and past issues are kept
+#[tracing::instrument(
+    name = "Archive a list",
+    skip(pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn archive_list(
+    list_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if set_archived(&pool, *list_id).await.map_err(e500)? {
+        FlashMessage::info("The list has been archived.").send();
+    } else {
+        FlashMessage::error("The default list and the archived lists cannot be archived.").send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+/// Returns `false` when the name is already taken
+#[tracing::instrument(
+    name = "Insert list",
+    skip(pool)
+)]
+async fn insert_list(pool: &PgPool, name: &ListName) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+            INSERT INTO lists (list_id, name, created_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name.as_ref()
+    )
+        .execute(pool)
+        .await
+        .context("Failed to insert the list")?
+        .rows_affected();
+    Ok(inserted == 1)
+}
+
+enum RenameOutcome {
+    Renamed,
+    NameTaken,
+    NotFound,
+}
+
+#[tracing::instrument(
+    name = "Update list name",
+    skip(pool)
+)]
+async fn update_list_name(
+    pool: &PgPool,
+    list_id: Uuid,
+    name: &ListName,
+) -> Result<RenameOutcome, anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"UPDATE lists SET name = $2 WHERE list_id = $1"#,
+        list_id,
+        name.as_ref()
+    )
+        .execute(pool)
+        .await;
+    match updated {
+        Ok(result) if result.rows_affected() == 0 => Ok(RenameOutcome::NotFound),
+        Ok(_) => Ok(RenameOutcome::Renamed),
+        // Another list has the name, see the unique constraint on `lists.name`
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+            Ok(RenameOutcome::NameTaken)
+        },
+        Err(e) => Err(e).context("Failed to rename the list"),
+    }
+}
+
+/// Returns `false` when the list is the default one, is already archived or does not exist
+#[tracing::instrument(
+    name = "Archive list",
+    skip(pool)
+)]
+async fn set_archived(pool: &PgPool, list_id: Uuid) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"
+            UPDATE lists
+            SET archived_at = now()
+            WHERE list_id = $1 AND NOT is_default AND archived_at IS NULL
+        "#,
+        list_id
+    )
+        .execute(pool)
+        .await
+        .context("Failed to archive the list")?
+        .rows_affected();
+    Ok(updated == 1)
+}
@@ -1,7 +1,19 @@
 mod dashboard;
 mod password;
 mod logout;
+mod dead_letters;
+mod newsletters;
+mod issues;
+mod lists;
+mod segments;
+mod subscribers;
 
 pub use dashboard::*;
 pub use password::*;
-pub use logout::*;
\ No newline at end of file
+pub use logout::*;
+pub use dead_letters::*;
+pub use newsletters::*;
+pub use issues::*;
+pub use lists::*;
+pub use segments::*;
+pub use subscribers::*;
\ No newline at end of file
@@ -0,0 +1,104 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::email_body::html_to_text;
+use crate::routes::{get_active_lists, get_segment_options};
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+#[derive(serde::Deserialize)]
+pub struct ComposeQuery {
+    // Draft to re-open, a blank form when missing
+    draft_id: Option<Uuid>,
+}
+
+pub struct Draft {
+    pub subject: String,
+    pub html_content: Option<String>,
+    pub text_content: String,
+    pub category: String,
+    pub send_at: Option<DateTime<Utc>>,
+    pub list_id: Uuid,
+    pub segment_id: Option<Uuid>,
+}
+
+/// Compose form of a new issue (or of a draft), submitted to `publish_newsletter_from_form`
+pub async fn publish_newsletter_form(
+    query: web::Query<ComposeQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match query.draft_id {
+        Some(draft_id) => match get_draft(&pool, draft_id).await.map_err(e500)? {
+            Some(draft) => Some(draft),
+            None => return Ok(HttpResponse::NotFound().finish()),
+        },
+        None => None,
+    };
+    let draft_id = query.draft_id.map(|id| id.to_string()).unwrap_or_default();
+    let subject = draft.as_ref().map(|d| d.subject.as_str()).unwrap_or_default();
+    let html_content = draft.as_ref().and_then(|d| d.html_content.as_deref()).unwrap_or_default();
+    // A text part generated from the HTML one is left out, to be generated again on save
+    let text_content = draft
+        .as_ref()
+        .filter(|d| d.html_content.as_deref().map(html_to_text).as_ref() != Some(&d.text_content))
+        .map(|d| d.text_content.as_str())
+        .unwrap_or_default();
+    let category = draft.as_ref().map(|d| d.category.as_str()).unwrap_or_default();
+    let send_at = draft
+        .as_ref()
+        .and_then(|d| d.send_at)
+        .map(|s| s.format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_default();
+    let lists = get_active_lists(&pool).await.map_err(e500)?;
+    // The default list is first, and selected by default
+    let list_id = draft.as_ref().map(|d| d.list_id).or_else(|| lists.first().map(|l| l.list_id));
+    let segments = get_segment_options(&pool).await.map_err(e500)?;
+    let segment_id = draft.as_ref().and_then(|d| d.segment_id);
+    // A new key for every rendering of the form: submitting the same form twice
+    // (e.g. a double click) publishes the issue only once
+    let idempotency_key = Uuid::new_v4();
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("subject", subject);
+    context.insert("html_content", html_content);
+    context.insert("text_content", text_content);
+    context.insert("category", category);
+    context.insert("send_at", &send_at);
+    context.insert("lists", &lists);
+    context.insert("list_id", &list_id);
+    context.insert("segments", &segments);
+    context.insert("segment_id", &segment_id);
+    context.insert("idempotency_key", &idempotency_key);
+    context.insert("draft_id", &draft_id);
+    render_page(&templates, "admin/newsletter_form.html", &context)
+}
+
+/// `None` when the issue does not exist or is not a draft anymore
+#[tracing::instrument(
+    name = "Get draft",
+    skip(pool)
+)]
+pub async fn get_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+            SELECT subject, html_content, text_content, category, send_at, list_id, segment_id
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the draft")?;
+    Ok(draft)
+}
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod scheduled;
+
+pub use get::*;
+pub use post::*;
+pub use scheduled::*;
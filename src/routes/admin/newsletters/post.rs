@@ -0,0 +1,180 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::email_body::EmailBody;
+use crate::idempotency::IdempotencyKey;
+use crate::routes::{get_active_list, get_draft, publish_issue, save_draft, segment_exists, NewIssue};
+use crate::utils::{e400, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterFormData {
+    subject: String,
+    html_content: String,
+    text_content: String,
+    category: String,
+    // Value of a `datetime-local` input, empty to publish right away
+    send_at: String,
+    // The default list when empty
+    #[serde(default)]
+    list_id: String,
+    // Everyone on the list when empty
+    #[serde(default)]
+    segment_id: String,
+    idempotency_key: String,
+    // Set when editing a draft, empty for a new issue
+    #[serde(default)]
+    draft_id: String,
+    #[serde(default)]
+    action: FormAction,
+}
+
+/// Which of the form buttons was used
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum FormAction {
+    #[default]
+    Publish,
+    Draft,
+}
+
+/// Publish (or schedule) the issue submitted through the compose form, or save it as a draft
+#[tracing::instrument(
+    name = "Publish a newsletter issue from the admin form",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_newsletter_from_form(
+    form: web::Form<NewsletterFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let NewsletterFormData {
+        subject,
+        html_content,
+        text_content,
+        category,
+        send_at,
+        list_id,
+        segment_id,
+        idempotency_key,
+        draft_id,
+        action,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let draft_id = match draft_id.trim() {
+        "" => None,
+        draft_id => Some(Uuid::parse_str(draft_id).map_err(e400)?),
+    };
+    // Back to the form, along with the draft being edited
+    let compose_form = match draft_id {
+        Some(draft_id) => format!("/admin/newsletters?draft_id={}", draft_id),
+        None => "/admin/newsletters".into(),
+    };
+
+    if subject.trim().is_empty() || category.trim().is_empty() {
+        FlashMessage::error("The subject and the category are required.").send();
+        return Ok(see_other(&compose_form));
+    }
+    let content = match EmailBody::parse(Some(html_content), Some(text_content)) {
+        Ok(content) => content,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&compose_form));
+        }
+    };
+    let send_at = match parse_send_at(&send_at) {
+        Ok(send_at) => send_at,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&compose_form));
+        }
+    };
+    let list_id = match list_id.trim() {
+        "" => None,
+        list_id => Some(Uuid::parse_str(list_id).map_err(e400)?),
+    };
+    let list_id = match get_active_list(&pool, list_id).await.map_err(e500)? {
+        Some(list_id) => list_id,
+        None => {
+            FlashMessage::error("The list cannot be published to.").send();
+            return Ok(see_other(&compose_form));
+        }
+    };
+    let segment_id = match segment_id.trim() {
+        "" => None,
+        segment_id => Some(Uuid::parse_str(segment_id).map_err(e400)?),
+    };
+    if let Some(segment_id) = segment_id {
+        if !segment_exists(&pool, segment_id).await.map_err(e500)? {
+            FlashMessage::error("The segment does not exist.").send();
+            return Ok(see_other(&compose_form));
+        }
+    }
+    if let Some(draft_id) = draft_id {
+        if get_draft(&pool, draft_id).await.map_err(e500)?.is_none() {
+            FlashMessage::error("This issue is not a draft anymore.").send();
+            return Ok(see_other("/admin/issues"));
+        }
+    }
+
+    let issue = NewIssue {
+        subject,
+        content,
+        category,
+        send_at,
+        list_id,
+        segment_id,
+        draft_id,
+    };
+    if let Err(e) = issue.validate_merge_tags() {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&compose_form));
+    }
+    if action == FormAction::Draft {
+        let response = save_draft(
+            &pool,
+            *user_id,
+            Some(idempotency_key),
+            &issue,
+            see_other("/admin/issues")
+        )
+            .await
+            .map_err(e500)?;
+        FlashMessage::info("The draft has been saved.").send();
+        return Ok(response);
+    }
+    let response = publish_issue(
+        &pool,
+        *user_id,
+        Some(idempotency_key),
+        &issue,
+        see_other("/admin/newsletters")
+    )
+        .await
+        .map_err(e500)?;
+    // Also sent when a retried submission gets the saved response back
+    match send_at.filter(|send_at| *send_at > Utc::now()) {
+        Some(send_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            send_at.to_rfc3339()
+        )).send(),
+        None => FlashMessage::info("The newsletter issue has been published!").send(),
+    }
+    Ok(response)
+}
+
+/// `datetime-local` inputs have no timezone, the value is read as UTC
+fn parse_send_at(send_at: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let send_at = send_at.trim();
+    if send_at.is_empty() {
+        return Ok(None);
+    }
+    NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(send_at, "%Y-%m-%dT%H:%M:%S"))
+        .map(|send_at| Some(DateTime::from_naive_utc_and_offset(send_at, Utc)))
+        .map_err(|_| format!("{} is not a valid date and time.", send_at))
+}
@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+#[derive(serde::Serialize)]
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    subject: String,
+    category: String,
+    send_at: Option<DateTime<Utc>>,
+}
+
+/// List the issues waiting for their `send_at` to be published
+pub async fn scheduled_newsletters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("issues", &issues);
+    render_page(&templates, "admin/scheduled_newsletters.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get scheduled issues",
+    skip(pool)
+)]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+            SELECT newsletter_issue_id, subject, category, send_at
+            FROM newsletter_issues
+            WHERE status = 'scheduled'
+            ORDER BY send_at
+        "#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the scheduled issues")?;
+    Ok(issues)
+}
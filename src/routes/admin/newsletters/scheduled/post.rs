@@ -0,0 +1,55 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct CancelFormData {
+    newsletter_issue_id: Uuid,
+}
+
+/// Cancel a scheduled issue, as long as the `issue_scheduler` has not published it yet
+#[tracing::instrument(
+    name = "Cancel a scheduled issue",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn cancel_scheduled_newsletter(
+    form: web::Form<CancelFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let cancelled = cancel(&pool, form.newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    if cancelled {
+        FlashMessage::info("The scheduled issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("The issue is not scheduled anymore, it may have already been sent.").send();
+    }
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
+
+async fn cancel(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = 'cancelled'
+            WHERE
+                newsletter_issue_id = $1 AND
+                status = 'scheduled'
+        "#,
+        newsletter_issue_id
+    )
+        .execute(pool)
+        .await
+        .context("Failed to cancel the scheduled issue")?
+        .rows_affected();
+    Ok(n_updated_rows > 0)
+}
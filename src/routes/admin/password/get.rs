@@ -1,68 +1,20 @@
-use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use crate::session_state::TypedSession;
-use crate::utils::{e500, see_other};
-use std::fmt::Write;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page, see_other};
 
 pub async fn change_password_form(
     session: TypedSession,
-    flash_messages: IncomingFlashMessages
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     }
 
-    let mut msg_html = String::new();
-
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-
-    Ok(
-        HttpResponse::Ok().content_type(ContentType::html())
-            .body(
-                format!(r#"
-                <!DOCTYPE html>
-                <html lang="en">
-                <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Change Password</title>
-                </head>
-                <body>
-                {msg_html}
-                <form action="/admin/password" method="post">
-                <label>Current password
-                <input
-                type="password"
-                placeholder="Enter current password"
-                name="current_password"
-                >
-                </label>
-                <br>
-                <label>New password
-                <input
-                type="password"
-                placeholder="Enter new password"
-                name="new_password"
-                >
-                </label>
-                <br>
-                <label>Confirm new password
-                <input
-                type="password"
-                placeholder="Type the new password again"
-                name="new_password_check"
-                >
-                </label>
-                <br>
-                <button type="submit">Change password</button>
-                </form>
-                <p><a href="/admin/dashboard">&lt;- Back</a></p>
-                </body>
-                </html>
-                "#
-            ))
-    )
-}
\ No newline at end of file
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    render_page(&templates, "admin/password.html", &context)
+}
@@ -9,7 +9,7 @@ use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 
 #[derive(serde::Deserialize)]
-pub struct FormData {
+pub struct ChangePasswordFormData {
     current_password: Secret<String>,
     new_password: Secret<String>,
     new_password_check: Secret<String>
@@ -27,7 +27,7 @@ async fn reject_anonymous_users(session: TypedSession) -> Result<uuid::Uuid, act
 }
 
 pub async fn change_password(
-    form: web::Form<FormData>,
+    form: web::Form<ChangePasswordFormData>,
     session: TypedSession,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -54,7 +54,7 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             },
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         }
     }
 
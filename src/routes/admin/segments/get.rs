@@ -0,0 +1,143 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::domain::segment_filter::{bind_sql_values, SegmentFilter};
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+#[derive(serde::Serialize)]
+struct SegmentSummary {
+    segment_id: Uuid,
+    name: String,
+    filter: String,
+    created_at: DateTime<Utc>,
+    audience: SegmentAudience,
+}
+
+/// A segment issues can be sent to, offered by the compose form
+#[derive(serde::Serialize)]
+pub struct SegmentOption {
+    pub segment_id: Uuid,
+    pub name: String,
+}
+
+/// How many subscribers a filter matches
+#[derive(serde::Serialize)]
+pub struct SegmentAudience {
+    pub subscribers: i64,
+    // The ones an issue can be sent to
+    pub confirmed_subscribers: i64,
+}
+
+/// The saved segments along with the size of their audience, and the form to create one
+pub async fn segments_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut segments = Vec::new();
+    for segment in get_segments(&pool).await.map_err(e500)? {
+        let filter: SegmentFilter = serde_json::from_value(segment.filter)
+            .context("Failed to parse a stored segment filter")
+            .map_err(e500)?;
+        segments.push(SegmentSummary {
+            audience: count_segment_audience(&pool, &filter).await.map_err(e500)?,
+            filter: serde_json::to_string_pretty(&filter).map_err(e500)?,
+            segment_id: segment.segment_id,
+            name: segment.name,
+            created_at: segment.created_at,
+        });
+    }
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("segments", &segments);
+    render_page(&templates, "admin/segments.html", &context)
+}
+
+struct SegmentRow {
+    segment_id: Uuid,
+    name: String,
+    filter: serde_json::Value,
+    created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Get segments",
+    skip(pool)
+)]
+async fn get_segments(pool: &PgPool) -> Result<Vec<SegmentRow>, anyhow::Error> {
+    let segments = sqlx::query_as!(
+        SegmentRow,
+        r#"
+            SELECT segment_id, name, filter, created_at
+            FROM segments
+            ORDER BY name
+        "#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the segments")?;
+    Ok(segments)
+}
+
+#[tracing::instrument(
+    name = "Get segment options",
+    skip(pool)
+)]
+pub async fn get_segment_options(pool: &PgPool) -> Result<Vec<SegmentOption>, anyhow::Error> {
+    let segments = sqlx::query_as!(
+        SegmentOption,
+        r#"SELECT segment_id, name FROM segments ORDER BY name"#
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the segments")?;
+    Ok(segments)
+}
+
+#[tracing::instrument(
+    name = "Check segment exists",
+    skip(pool)
+)]
+pub async fn segment_exists(pool: &PgPool, segment_id: Uuid) -> Result<bool, anyhow::Error> {
+    let segment = sqlx::query!(
+        r#"SELECT segment_id FROM segments WHERE segment_id = $1"#,
+        segment_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the segment")?;
+    Ok(segment.is_some())
+}
+
+#[tracing::instrument(
+    name = "Count segment audience",
+    skip(pool)
+)]
+pub async fn count_segment_audience(
+    pool: &PgPool,
+    filter: &SegmentFilter,
+) -> Result<SegmentAudience, anyhow::Error> {
+    let (condition, values) = filter.to_sql(1);
+    let sql = format!(
+        r#"
+            SELECT
+                COUNT(*),
+                COUNT(*) FILTER (WHERE s.status = 'confirmed')
+            FROM subscriptions s
+            WHERE {}
+        "#,
+        condition
+    );
+    let row = bind_sql_values(sqlx::query(&sql), &values)
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the segment audience")?;
+    Ok(SegmentAudience {
+        subscribers: row.try_get(0)?,
+        confirmed_subscribers: row.try_get(1)?,
+    })
+}
@@ -0,0 +1,93 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::segment_filter::SegmentFilter;
+use crate::domain::segment_name::SegmentName;
+use crate::routes::count_segment_audience;
+use crate::utils::{e400, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct SegmentFormData {
+    name: String,
+    // JSON, see `domain::segment_filter`
+    filter: String,
+}
+
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_segment(
+    form: web::Form<SegmentFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match SegmentName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+    let filter = match SegmentFilter::parse(&form.0.filter) {
+        Ok(filter) => filter,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+    if insert_segment(&pool, &name, &filter).await.map_err(e500)? {
+        FlashMessage::info(format!("The segment {} has been created.", name.as_ref())).send();
+    } else {
+        FlashMessage::error(format!("A segment named {} already exists.", name.as_ref())).send();
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+/// Size of the audience of a filter before saving it, the filter (JSON) is the request body
+#[tracing::instrument(
+    name = "Preview a segment",
+    skip(body, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn preview_segment(
+    body: String,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = SegmentFilter::parse(&body).map_err(e400)?;
+    let audience = count_segment_audience(&pool, &filter).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(audience))
+}
+
+/// Returns `false` when the name is already taken
+#[tracing::instrument(
+    name = "Insert segment",
+    skip(pool, filter)
+)]
+async fn insert_segment(
+    pool: &PgPool,
+    name: &SegmentName,
+    filter: &SegmentFilter,
+) -> Result<bool, anyhow::Error> {
+    let filter = serde_json::to_value(filter).context("Failed to serialize the segment filter")?;
+    let inserted = sqlx::query!(
+        r#"
+            INSERT INTO segments (segment_id, name, filter, created_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name.as_ref(),
+        filter
+    )
+        .execute(pool)
+        .await
+        .context("Failed to insert the segment")?
+        .rows_affected();
+    Ok(inserted == 1)
+}
@@ -0,0 +1,329 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::email_client::EmailClient;
+use crate::routes::{
+    confirm_list_memberships,
+    confirm_subscriber,
+    generate_subscription_token,
+    leave_every_list,
+    record_audit_event,
+    send_confirmation_email,
+    store_token,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::templates::Templates;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RenameFormData {
+    name: String,
+}
+
+pub struct LockedSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+}
+
+/// Confirm a subscriber pending confirmation, as if they had followed the confirmation link
+#[tracing::instrument(
+    name = "Confirm a subscriber from the admin pages",
+    skip(pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn confirm_subscriber_manually(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status != "pending_confirmation" {
+        FlashMessage::error("Only the subscribers pending confirmation can be confirmed.").send();
+        return Ok(see_other(&subscriber_page));
+    }
+    confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to confirm the subscriber")
+        .map_err(e500)?;
+    confirm_list_memberships(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to confirm the list memberships")
+        .map_err(e500)?;
+    // The confirmation links already sent have become useless
+    sqlx::query!(
+        r#"
+            UPDATE subscription_tokens
+            SET consumed_at = now()
+            WHERE subscription_id = $1 AND consumed_at IS NULL
+        "#,
+        subscriber_id
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to consume the confirmation tokens")
+        .map_err(e500)?;
+    record_audit_event(&mut transaction, **user_id, &subscriber, "confirm", None)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(see_other(&subscriber_page))
+}
+
+/// Unsubscribe a subscriber from every list, as the unsubscribe link does
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber from the admin pages",
+    skip(pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn unsubscribe_subscriber_manually(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status == "unsubscribed" {
+        FlashMessage::error("The subscriber has already unsubscribed.").send();
+        return Ok(see_other(&subscriber_page));
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to unsubscribe the subscriber")
+        .map_err(e500)?;
+    leave_every_list(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber from the lists")
+        .map_err(e500)?;
+    record_audit_event(&mut transaction, **user_id, &subscriber, "unsubscribe", None)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(see_other(&subscriber_page))
+}
+
+/// Send a new confirmation link, e.g. when the first one expired or got lost
+#[tracing::instrument(
+    name = "Resend the confirmation email from the admin pages",
+    skip(pool, email_client, templates, base_url),
+    fields(user_id=%*user_id)
+)]
+pub async fn resend_confirmation(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<Templates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if subscriber.status != "pending_confirmation" {
+        FlashMessage::error("Only the subscribers pending confirmation can be sent a confirmation email.").send();
+        return Ok(see_other(&subscriber_page));
+    }
+    let (email, name) = match (
+        SubscriberEmail::parse(subscriber.email.clone()),
+        SubscriberName::parse(subscriber.name.clone()),
+    ) {
+        (Ok(email), Ok(name)) => (email, name),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::warn!(error.message = %e, "The stored subscriber details are invalid");
+            FlashMessage::error(
+                "The stored email or name of the subscriber is not valid, fix it before resending the confirmation email."
+            ).send();
+            return Ok(see_other(&subscriber_page));
+        }
+    };
+    let new_subscriber = NewSubscriber {
+        email,
+        name,
+        attributes: Default::default(),
+        tags: Vec::new(),
+    };
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store the confirmation token")
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    // The token is committed before sending, so that the link works as soon as the email lands.
+    // The audit entry is recorded afterwards, to say whether the email actually went out.
+    let send_outcome = send_confirmation_email(
+        &email_client,
+        &templates,
+        new_subscriber,
+        &base_url.0,
+        &subscription_token
+    )
+        .await;
+    let details = match &send_outcome {
+        Ok(()) => None,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the confirmation email"
+            );
+            Some("email not sent")
+        }
+    };
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    record_audit_event(&mut transaction, **user_id, &subscriber, "resend_confirmation", details)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    match send_outcome {
+        Ok(()) => FlashMessage::info("A new confirmation email has been sent.").send(),
+        Err(_) => FlashMessage::error("The confirmation email could not be sent, try again later.").send(),
+    }
+    Ok(see_other(&subscriber_page))
+}
+
+#[tracing::instrument(
+    name = "Rename a subscriber from the admin pages",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn rename_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<RenameFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    let name = match SubscriberName::parse(form.0.name.trim().to_owned()) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(format!("{}.", e)).send();
+            return Ok(see_other(&subscriber_page));
+        }
+    };
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref()
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to rename the subscriber")
+        .map_err(e500)?;
+    let details = format!("{} -> {}", subscriber.name, name.as_ref());
+    record_audit_event(&mut transaction, **user_id, &subscriber, "rename", Some(&details))
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    FlashMessage::info(format!("The subscriber has been renamed to {}.", name.as_ref())).send();
+    Ok(see_other(&subscriber_page))
+}
+
+/// Delete the subscriber for good, along with the confirmation tokens, list memberships and
+/// preferences. The audit trail keeps the address, the deliveries report keeps the emails sent
+#[tracing::instrument(
+    name = "Delete a subscriber from the admin pages",
+    skip(pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let subscriber = match lock_subscriber(&mut transaction, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscription_id = $1"#,
+        subscriber_id
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the confirmation tokens")
+        .map_err(e500)?;
+    // The list memberships and the category opt-outs are deleted in cascade
+    sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(e500)?;
+    record_audit_event(&mut transaction, **user_id, &subscriber, "delete", None)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    FlashMessage::info(format!("The subscriber {} has been deleted.", subscriber.email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, anyhow::Error> {
+    pool.begin()
+        .await
+        .context("Failed to acquire Database Connection From the pool")
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), anyhow::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction of the subscriber action")
+}
+
+/// The row is locked until the end of the transaction, concurrent actions are applied one at a time
+#[tracing::instrument(
+    name = "Lock subscriber",
+    skip(transaction)
+)]
+async fn lock_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<LockedSubscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        LockedSubscriber,
+        r#"SELECT id, email, name, status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+        .fetch_optional(transaction)
+        .await
+        .context("Failed to fetch the subscriber")?;
+    Ok(subscriber)
+}
@@ -0,0 +1,151 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct AttributesQuery {
+    // The lookup form only when missing
+    email: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributesFormData {
+    email: String,
+    // Comma separated
+    tags: String,
+    // JSON object, empty to remove every attribute
+    attributes: String,
+}
+
+struct SubscriberDetails {
+    id: Uuid,
+    email: String,
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+/// Tags and attributes of a subscriber, looked up by email
+pub async fn subscriber_attributes_page(
+    query: web::Query<AttributesQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    if let Some(email) = query.0.email.as_deref().map(str::trim).filter(|email| !email.is_empty()) {
+        let subscriber = get_subscriber_details(&pool, email).await.map_err(e500)?;
+        if let Some(subscriber) = subscriber {
+            let attributes = serde_json::to_string_pretty(&subscriber.attributes).map_err(e500)?;
+            context.insert("subscriber_email", &subscriber.email);
+            context.insert("tags", &subscriber.tags.join(", "));
+            context.insert("attributes", &attributes);
+        }
+        context.insert("email", email);
+    }
+    render_page(&templates, "admin/subscriber_attributes.html", &context)
+}
+
+/// Replace the tags and attributes of a subscriber
+#[tracing::instrument(
+    name = "Update subscriber attributes",
+    skip(form, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn update_subscriber_attributes(
+    form: web::Form<AttributesFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AttributesFormData { email, tags, attributes } = form.0;
+    // As looked up by the page
+    let email = email.trim();
+    let attributes_page = format!(
+        "/admin/subscribers/attributes?email={}",
+        urlencoding::encode(email)
+    );
+    let tags = match SubscriberTag::parse_list(&tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&attributes_page));
+        }
+    };
+    let attributes = match parse_attributes(&attributes) {
+        Ok(attributes) => attributes,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&attributes_page));
+        }
+    };
+    let subscriber = match get_subscriber_details(&pool, email).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => {
+            FlashMessage::error(format!("There is no subscriber with the address {}.", email)).send();
+            return Ok(see_other("/admin/subscribers/attributes"));
+        }
+    };
+    store_attributes(&pool, subscriber.id, attributes, &tags)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("The tags and attributes have been saved.").send();
+    Ok(see_other(&attributes_page))
+}
+
+fn parse_attributes(attributes: &str) -> Result<SubscriberAttributes, String> {
+    if attributes.trim().is_empty() {
+        return Ok(SubscriberAttributes::default());
+    }
+    let attributes: serde_json::Map<String, serde_json::Value> = serde_json::from_str(attributes)
+        .map_err(|e| format!("The attributes must be a JSON object: {}", e))?;
+    SubscriberAttributes::parse(attributes)
+}
+
+#[tracing::instrument(
+    name = "Get subscriber details",
+    skip(pool, email)
+)]
+async fn get_subscriber_details(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"SELECT id, email, attributes, tags FROM subscriptions WHERE email = $1"#,
+        email
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the subscriber")?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(
+    name = "Store subscriber attributes",
+    skip(pool, attributes, tags)
+)]
+async fn store_attributes(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    attributes: SubscriberAttributes,
+    tags: &[SubscriberTag],
+) -> Result<(), anyhow::Error> {
+    let tags: Vec<String> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = $2, tags = $3 WHERE id = $1"#,
+        subscriber_id,
+        attributes.into_value(),
+        &tags
+    )
+        .execute(pool)
+        .await
+        .context("Failed to update the subscriber attributes")?;
+    Ok(())
+}
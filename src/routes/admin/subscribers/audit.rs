@@ -0,0 +1,108 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::routes::LockedSubscriber;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+/// Number of entries listed by the audit trail, most recent first
+const AUDIT_ENTRIES_LENGTH: i64 = 100;
+
+#[derive(serde::Serialize)]
+pub struct AuditEntry {
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    // Of the admin who took the action
+    username: String,
+    action: String,
+    details: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+/// Latest actions of the admins on the subscribers, deleted ones included
+pub async fn subscriber_audit_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = get_audit_entries(&pool, None).await.map_err(e500)?;
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("entries", &entries);
+    render_page(&templates, "admin/subscriber_audit.html", &context)
+}
+
+/// Part of the transaction of the action, so that no action goes unrecorded
+#[tracing::instrument(
+    name = "Record subscriber audit event",
+    skip(transaction, subscriber, details)
+)]
+pub async fn record_audit_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    subscriber: &LockedSubscriber,
+    action: &str,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_audit_log (
+                audit_id,
+                subscriber_id,
+                subscriber_email,
+                user_id,
+                action,
+                details,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber.id,
+        subscriber.email,
+        user_id,
+        action,
+        details
+    )
+        .execute(transaction)
+        .await
+        .context("Failed to record the audit event")?;
+    Ok(())
+}
+
+/// Every subscriber when `subscriber_id` is `None`
+#[tracing::instrument(
+    name = "Get subscriber audit entries",
+    skip(pool)
+)]
+pub async fn get_audit_entries(
+    pool: &PgPool,
+    subscriber_id: Option<Uuid>,
+) -> Result<Vec<AuditEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+            SELECT
+                a.subscriber_id,
+                a.subscriber_email,
+                u.username,
+                a.action,
+                a.details,
+                a.created_at
+            FROM subscriber_audit_log a
+            JOIN users u USING (user_id)
+            WHERE $1::uuid IS NULL OR a.subscriber_id = $1
+            ORDER BY a.created_at DESC
+            LIMIT $2
+        "#,
+        subscriber_id,
+        AUDIT_ENTRIES_LENGTH
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the audit entries")?;
+    Ok(entries)
+}
@@ -0,0 +1,216 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::routes::get_audit_entries;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page};
+
+/// Number of deliveries shown on the detail page, most recent first
+const DELIVERY_HISTORY_LENGTH: i64 = 100;
+
+#[derive(serde::Serialize)]
+struct SubscriberDetails {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    paused_until: Option<DateTime<Utc>>,
+    unsubscribe_token: String,
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+/// Confirmation token sent by the subscribe endpoint
+#[derive(serde::Serialize)]
+struct ConfirmationToken {
+    subscription_token: String,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct ListMembership {
+    name: String,
+    status: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    newsletter_issue_id: Uuid,
+    subject: String,
+    status: String,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+/// Everything known about a subscriber: tokens, lists, preferences, delivery history and
+/// the actions taken by the admins, along with the forms to take new ones
+pub async fn subscriber_page(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let tokens = get_confirmation_tokens(&pool, subscriber_id).await.map_err(e500)?;
+    let memberships = get_list_memberships(&pool, subscriber_id).await.map_err(e500)?;
+    let opt_outs = get_category_opt_outs(&pool, subscriber_id).await.map_err(e500)?;
+    let deliveries = get_delivery_history(&pool, &subscriber.email).await.map_err(e500)?;
+    let audit_entries = get_audit_entries(&pool, Some(subscriber_id)).await.map_err(e500)?;
+    let attributes = serde_json::to_string_pretty(&subscriber.attributes).map_err(e500)?;
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("subscriber", &subscriber);
+    context.insert("attributes", &attributes);
+    context.insert("attributes_page", &format!(
+        "/admin/subscribers/attributes?email={}",
+        urlencoding::encode(&subscriber.email)
+    ));
+    context.insert("tokens", &tokens);
+    context.insert("memberships", &memberships);
+    context.insert("opt_outs", &opt_outs);
+    context.insert("deliveries", &deliveries);
+    context.insert("audit_entries", &audit_entries);
+    render_page(&templates, "admin/subscriber.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Get subscriber",
+    skip(pool)
+)]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+            SELECT
+                id,
+                email,
+                name,
+                status,
+                subscribed_at,
+                paused_until,
+                unsubscribe_token,
+                attributes,
+                tags
+            FROM subscriptions
+            WHERE id = $1
+        "#,
+        subscriber_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the subscriber")?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(
+    name = "Get confirmation tokens",
+    skip(pool)
+)]
+async fn get_confirmation_tokens(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConfirmationToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+            SELECT subscription_token, created_at, consumed_at
+            FROM subscription_tokens
+            WHERE subscription_id = $1
+            ORDER BY created_at DESC
+        "#,
+        subscriber_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the confirmation tokens")?;
+    Ok(tokens)
+}
+
+#[tracing::instrument(
+    name = "Get list memberships",
+    skip(pool)
+)]
+async fn get_list_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, anyhow::Error> {
+    let memberships = sqlx::query_as!(
+        ListMembership,
+        r#"
+            SELECT l.name, m.status, m.created_at
+            FROM list_memberships m
+            JOIN lists l USING (list_id)
+            WHERE m.subscription_id = $1
+            ORDER BY l.is_default DESC, l.name
+        "#,
+        subscriber_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the list memberships")?;
+    Ok(memberships)
+}
+
+#[tracing::instrument(
+    name = "Get subscriber category opt-outs",
+    skip(pool)
+)]
+async fn get_category_opt_outs(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let opt_outs = sqlx::query!(
+        r#"
+            SELECT category
+            FROM subscription_category_opt_outs
+            WHERE subscription_id = $1
+            ORDER BY category
+        "#,
+        subscriber_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the category opt-outs")?;
+    Ok(opt_outs.into_iter().map(|o| o.category).collect())
+}
+
+#[tracing::instrument(
+    name = "Get delivery history",
+    skip(pool, email)
+)]
+async fn get_delivery_history(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<DeliveryRecord>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+            SELECT d.newsletter_issue_id, i.subject, d.status, d.last_error, d.updated_at
+            FROM deliveries d
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            WHERE d.subscriber_email = $1
+            ORDER BY d.updated_at DESC
+            LIMIT $2
+        "#,
+        email,
+        DELIVERY_HISTORY_LENGTH
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the delivery history")?;
+    Ok(deliveries)
+}
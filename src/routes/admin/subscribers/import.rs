@@ -0,0 +1,617 @@
+use std::collections::HashMap;
+use actix_multipart::{Field, Multipart, MultipartError};
+use actix_web::http::header::CONTENT_DISPOSITION;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_import::{csv_line, CsvRecord, CsvRecordReader, ImportColumns, ImportMode};
+use crate::domain::subscriber_name::SubscriberName;
+use crate::email_client::EmailClient;
+use crate::routes::{
+    add_to_list,
+    confirm_list_memberships,
+    confirm_subscriber,
+    generate_subscription_token,
+    get_active_list,
+    get_active_lists,
+    get_subscriber_by_email,
+    insert_subscriber,
+    record_audit_event,
+    send_confirmation_email,
+    store_token,
+    LockedSubscriber,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::templates::Templates;
+use crate::utils::{e400, e500, flash_messages_content, render_page, see_other};
+
+/// Upper bound of the size of an imported file
+const MAX_IMPORT_FILE_MEGABYTES: usize = 10;
+
+/// Upper bound of the number of rows of an imported file, header excluded
+const MAX_IMPORT_ROWS: usize = 10_000;
+
+/// Number of confirmation emails of an import sent at the same time
+const CONFIRMATION_EMAILS_CONCURRENCY: usize = 10;
+
+/// Number of imports listed by the import page, most recent first
+const IMPORTS_LENGTH: i64 = 20;
+
+const IMPORT_PAGE: &str = "/admin/subscribers/import";
+
+#[derive(serde::Serialize)]
+struct ImportSummary {
+    import_id: Uuid,
+    file_name: String,
+    // Of the admin who imported the file
+    username: String,
+    list_name: String,
+    mode: String,
+    imported_count: i32,
+    skipped_count: i32,
+    rejected_count: i32,
+    created_at: DateTime<Utc>,
+    // `None` while the confirmation emails are being sent
+    completed_at: Option<DateTime<Utc>>,
+}
+
+/// The form fields and the rows of the uploaded file
+struct ImportUpload {
+    fields: HashMap<String, String>,
+    file_name: Option<String>,
+    records: Vec<CsvRecord>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum UploadError {
+    // Reported to the admin on the import page
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Multipart(#[from] MultipartError),
+}
+
+/// Outcome of a row of the file, a row of the results file
+struct ImportedRow {
+    row: usize,
+    email: String,
+    name: String,
+    result: &'static str,
+    message: String,
+}
+
+impl ImportedRow {
+    fn csv_line(&self) -> String {
+        csv_line(&[&self.row.to_string(), &self.email, &self.name, self.result, &self.message])
+    }
+}
+
+/// Confirmation email to send once the import is committed
+struct PendingConfirmation {
+    // Position of the subscriber's row in the results
+    index: usize,
+    new_subscriber: NewSubscriber,
+    subscription_token: String,
+}
+
+/// The upload form, along with the latest imports and their results
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_active_lists(&pool).await.map_err(e500)?;
+    let imports = get_imports(&pool).await.map_err(e500)?;
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("lists", &lists);
+    context.insert("imports", &imports);
+    context.insert("max_rows", &MAX_IMPORT_ROWS);
+    context.insert("max_megabytes", &MAX_IMPORT_FILE_MEGABYTES);
+    render_page(&templates, "admin/subscriber_import.html", &context)
+}
+
+/// Import the subscribers of a CSV file into a list.
+///
+/// The addresses already known (or repeated in the file) are skipped, the invalid rows are rejected:
+/// the outcome of every row is kept in a results file, downloadable from the import page.
+/// The confirmation emails are sent in the background, see `send_import_confirmations`
+#[tracing::instrument(
+    name = "Import subscribers from a CSV file",
+    skip(payload, pool, email_client, templates, base_url),
+    fields(user_id=%*user_id)
+)]
+pub async fn import_subscribers(
+    payload: Multipart,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<Templates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportUpload { fields, file_name, mut records } = match read_upload(payload).await {
+        Ok(upload) => upload,
+        Err(UploadError::Invalid(e)) => return Ok(import_error(e)),
+        Err(UploadError::Multipart(e)) => return Err(e400(e)),
+    };
+    let mode = match ImportMode::parse(fields.get("mode").map(String::as_str).unwrap_or_default()) {
+        Ok(mode) => mode,
+        Err(e) => return Ok(import_error(e)),
+    };
+    // The default list when missing
+    let list_id = match fields.get("list_id").filter(|id| !id.is_empty()).map(|id| id.parse()) {
+        None => None,
+        Some(Ok(list_id)) => Some(list_id),
+        Some(Err(_)) => return Ok(import_error("The list cannot be imported into.")),
+    };
+    let list_id = match get_active_list(&pool, list_id).await.map_err(e500)? {
+        Some(list_id) => list_id,
+        None => return Ok(import_error("The list cannot be imported into.")),
+    };
+    let file_name = match file_name {
+        Some(file_name) => file_name,
+        None => return Ok(import_error("Choose a CSV file to import.")),
+    };
+    if records.is_empty() {
+        return Ok(import_error("The file is empty."));
+    }
+    let columns = match records.remove(0).and_then(|header| ImportColumns::from_header(&header)) {
+        Ok(columns) => columns,
+        Err(e) => return Ok(import_error(e)),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Database Connection From the pool")
+        .map_err(e500)?;
+    let (rows, confirmations) = import_rows(
+        &mut transaction,
+        **user_id,
+        list_id,
+        mode,
+        &columns,
+        records
+    )
+        .await
+        .map_err(e500)?;
+    let import_id = Uuid::new_v4();
+    let import = NewImport {
+        import_id,
+        user_id: **user_id,
+        list_id,
+        file_name: &file_name,
+        mode,
+        completed: confirmations.is_empty(),
+    };
+    save_import(&mut transaction, &import, &rows).await.map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction of the import")
+        .map_err(e500)?;
+
+    let mut message = format!(
+        "The import of {} is done: {} imported, {} skipped, {} rejected.",
+        file_name,
+        count_rows(&rows, "imported"),
+        count_rows(&rows, "skipped"),
+        count_rows(&rows, "rejected")
+    );
+    if !confirmations.is_empty() {
+        message.push_str(" The confirmation emails are being sent.");
+        tokio::spawn(send_import_confirmations(
+            pool.get_ref().clone(),
+            email_client,
+            templates,
+            base_url.0.clone(),
+            import_id,
+            rows,
+            confirmations
+        ));
+    }
+    FlashMessage::info(message).send();
+    Ok(see_other(IMPORT_PAGE))
+}
+
+/// The results file of an import: the row, email, name, result and message of every row
+pub async fn import_results(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let import = sqlx::query!(
+        r#"SELECT results FROM subscriber_imports WHERE import_id = $1"#,
+        import_id
+    )
+        .fetch_optional(pool.get_ref())
+        .await
+        .context("Failed to fetch the results of the import")
+        .map_err(e500)?;
+    match import {
+        Some(import) => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"import-{}.csv\"", import_id)
+            ))
+            .body(import.results)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+fn count_rows(rows: &[ImportedRow], result: &str) -> usize {
+    rows.iter().filter(|r| r.result == result).count()
+}
+
+fn import_error(e: impl std::fmt::Display) -> HttpResponse {
+    FlashMessage::error(e.to_string()).send();
+    see_other(IMPORT_PAGE)
+}
+
+/// Read the form fields, and the rows of the file as it is uploaded
+async fn read_upload(mut payload: Multipart) -> Result<ImportUpload, UploadError> {
+    let mut upload = ImportUpload {
+        fields: HashMap::new(),
+        file_name: None,
+        records: Vec::new(),
+    };
+    while let Some(mut field) = payload.try_next().await? {
+        let file_name = field
+            .content_disposition()
+            .and_then(|d| d.get_filename())
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned);
+        let name = field.name().unwrap_or_default().to_owned();
+        match (name.as_str(), file_name) {
+            ("file", Some(file_name)) => {
+                upload.records = read_csv_file(&mut field).await?;
+                upload.file_name = Some(file_name);
+            },
+            // The file input left empty
+            ("file", None) | ("", _) => while field.try_next().await?.is_some() {},
+            _ => {
+                let value = read_text_field(&mut field).await?;
+                upload.fields.insert(name, value);
+            },
+        }
+    }
+    Ok(upload)
+}
+
+async fn read_csv_file(field: &mut Field) -> Result<Vec<CsvRecord>, UploadError> {
+    let mut reader = CsvRecordReader::default();
+    let mut records = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = field.try_next().await? {
+        size += chunk.len();
+        if size > MAX_IMPORT_FILE_MEGABYTES * 1024 * 1024 {
+            return Err(UploadError::Invalid(format!(
+                "The file cannot be larger than {} MB.",
+                MAX_IMPORT_FILE_MEGABYTES
+            )));
+        }
+        records.extend(reader.read(&chunk));
+        check_row_count(&records)?;
+    }
+    records.extend(reader.read(&[]));
+    check_row_count(&records)?;
+    Ok(records)
+}
+
+fn check_row_count(records: &[CsvRecord]) -> Result<(), UploadError> {
+    // The header row is not counted
+    if records.len() > MAX_IMPORT_ROWS + 1 {
+        return Err(UploadError::Invalid(format!(
+            "The file cannot have more than {} rows.",
+            MAX_IMPORT_ROWS
+        )));
+    }
+    Ok(())
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, UploadError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.try_next().await? {
+        value.extend_from_slice(&chunk);
+        if value.len() > 1024 {
+            return Err(UploadError::Invalid("The form is not valid.".into()));
+        }
+    }
+    String::from_utf8(value).map_err(|_| UploadError::Invalid("The form is not valid.".into()))
+}
+
+fn parse_row(email: &str, name: &str) -> Result<NewSubscriber, String> {
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(email.to_owned())?,
+        name: SubscriberName::parse(name.to_owned()).map_err(|e| format!("{}.", e))?,
+        attributes: Default::default(),
+        tags: Vec::new(),
+    })
+}
+
+/// A single transaction: an error leaves the subscribers as they were before the import
+#[tracing::instrument(
+    name = "Import the rows of a CSV file",
+    skip(transaction, columns, records)
+)]
+async fn import_rows(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    list_id: Uuid,
+    mode: ImportMode,
+    columns: &ImportColumns,
+    records: Vec<CsvRecord>,
+) -> Result<(Vec<ImportedRow>, Vec<PendingConfirmation>), anyhow::Error> {
+    let mut rows = Vec::with_capacity(records.len());
+    let mut confirmations = Vec::new();
+    // First row of each address, the later ones are skipped
+    let mut first_rows: HashMap<String, usize> = HashMap::new();
+    for (i, record) in records.into_iter().enumerate() {
+        let row = i + 1;
+        let (email, name) = match &record {
+            Ok(fields) => columns.select(fields),
+            Err(_) => (String::new(), String::new()),
+        };
+        let imported_row = |result, message| ImportedRow {
+            row,
+            email: email.clone(),
+            name: name.clone(),
+            result,
+            message,
+        };
+        let new_subscriber = match record.and_then(|_| parse_row(&email, &name)) {
+            Ok(new_subscriber) => new_subscriber,
+            Err(e) => {
+                rows.push(imported_row("rejected", e));
+                continue;
+            }
+        };
+        if let Some(first_row) = first_rows.get(&email) {
+            rows.push(imported_row("skipped", format!("Same address as row {}.", first_row)));
+            continue;
+        }
+        first_rows.insert(email.clone(), row);
+        let existing_subscriber = get_subscriber_by_email(transaction, &new_subscriber.email)
+            .await
+            .context("Failed to look up the subscriber in the database")?;
+        if let Some(subscriber) = existing_subscriber {
+            let message = format!("Already a subscriber, with the status {}.", subscriber.status);
+            rows.push(imported_row("skipped", message));
+            continue;
+        }
+
+        // Subscribed since the lookup, the unique `email` column deciding
+        let subscriber_id = match insert_subscriber(transaction, &new_subscriber)
+            .await
+            .context("Failed to add new subscriber into the database")? {
+            Some(subscriber_id) => subscriber_id,
+            None => {
+                rows.push(imported_row("skipped", "Already a subscriber.".into()));
+                continue;
+            }
+        };
+        add_to_list(transaction, list_id, subscriber_id)
+            .await
+            .context("Failed to add the subscriber to the list")?;
+        let status = match mode {
+            ImportMode::Confirmed => {
+                confirm_subscriber(transaction, subscriber_id)
+                    .await
+                    .context("Failed to confirm the subscriber")?;
+                confirm_list_memberships(transaction, subscriber_id)
+                    .await
+                    .context("Failed to confirm the list membership")?;
+                "confirmed"
+            },
+            ImportMode::SendConfirmation => {
+                let subscription_token = generate_subscription_token();
+                store_token(transaction, subscriber_id, &subscription_token)
+                    .await
+                    .context("Failed to store new subscriber token into the database")?;
+                confirmations.push(PendingConfirmation {
+                    index: rows.len(),
+                    new_subscriber,
+                    subscription_token,
+                });
+                "pending_confirmation"
+            },
+        };
+        let subscriber = LockedSubscriber {
+            id: subscriber_id,
+            email: email.clone(),
+            name: name.clone(),
+            status: status.into(),
+        };
+        record_audit_event(transaction, user_id, &subscriber, "import", Some(mode.as_str())).await?;
+        rows.push(imported_row("imported", String::new()));
+    }
+    Ok((rows, confirmations))
+}
+
+/// Send the confirmation emails of a committed import, then complete the import with its final results.
+///
+/// The subscribers whose email is not sent (e.g. the application stopped meanwhile) stay pending,
+/// the admins can resend the email from the subscriber page
+#[tracing::instrument(
+    name = "Send the confirmation emails of an import",
+    skip(pool, email_client, templates, base_url, rows, confirmations)
+)]
+async fn send_import_confirmations(
+    pool: PgPool,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<Templates>,
+    base_url: String,
+    import_id: Uuid,
+    mut rows: Vec<ImportedRow>,
+    confirmations: Vec<PendingConfirmation>,
+) {
+    for index in send_confirmation_emails(&email_client, &templates, &base_url, confirmations).await {
+        rows[index].message = "The confirmation email could not be sent, \
+            resend it from the subscriber page.".into();
+    }
+    if let Err(e) = complete_import(&pool, import_id, &rows).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to save the results of the confirmation emails of an import."
+        );
+    }
+}
+
+/// The positions of the rows whose email could not be sent: the subscribers stay pending,
+/// the admins can resend the email from the subscriber page
+async fn send_confirmation_emails(
+    email_client: &EmailClient,
+    templates: &Templates,
+    base_url: &str,
+    confirmations: Vec<PendingConfirmation>,
+) -> Vec<usize> {
+    futures_util::stream::iter(confirmations)
+        .map(|confirmation| async move {
+            let result = send_confirmation_email(
+                email_client,
+                templates,
+                confirmation.new_subscriber,
+                base_url,
+                &confirmation.subscription_token
+            ).await;
+            match result {
+                Ok(()) => None,
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send the confirmation email of an imported subscriber."
+                    );
+                    Some(confirmation.index)
+                }
+            }
+        })
+        .buffer_unordered(CONFIRMATION_EMAILS_CONCURRENCY)
+        .filter_map(|failed| async move { failed })
+        .collect()
+        .await
+}
+
+/// An import about to be saved, along with its rows
+struct NewImport<'a> {
+    import_id: Uuid,
+    user_id: Uuid,
+    list_id: Uuid,
+    file_name: &'a str,
+    mode: ImportMode,
+    // Not until its confirmation emails are sent
+    completed: bool,
+}
+
+fn results_file(rows: &[ImportedRow]) -> String {
+    std::iter::once(csv_line(&["row", "email", "name", "result", "message"]))
+        .chain(rows.iter().map(ImportedRow::csv_line))
+        .collect()
+}
+
+/// Saved along with the subscribers, so that an import cannot go unrecorded
+#[tracing::instrument(
+    name = "Save subscriber import",
+    skip(transaction, import, rows),
+    fields(import_id=%import.import_id)
+)]
+async fn save_import(
+    transaction: &mut Transaction<'_, Postgres>,
+    import: &NewImport<'_>,
+    rows: &[ImportedRow],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriber_imports (
+                import_id,
+                user_id,
+                list_id,
+                file_name,
+                mode,
+                imported_count,
+                skipped_count,
+                rejected_count,
+                results,
+                created_at,
+                completed_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now(), CASE WHEN $10 THEN now() END)
+        "#,
+        import.import_id,
+        import.user_id,
+        import.list_id,
+        import.file_name,
+        import.mode.as_str(),
+        count_rows(rows, "imported") as i32,
+        count_rows(rows, "skipped") as i32,
+        count_rows(rows, "rejected") as i32,
+        results_file(rows),
+        import.completed
+    )
+        .execute(transaction)
+        .await
+        .context("Failed to save the import")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Complete subscriber import",
+    skip(pool, rows)
+)]
+async fn complete_import(
+    pool: &PgPool,
+    import_id: Uuid,
+    rows: &[ImportedRow],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriber_imports SET results = $2, completed_at = now() WHERE import_id = $1"#,
+        import_id,
+        results_file(rows)
+    )
+        .execute(pool)
+        .await
+        .context("Failed to complete the import")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get subscriber imports",
+    skip(pool)
+)]
+async fn get_imports(pool: &PgPool) -> Result<Vec<ImportSummary>, anyhow::Error> {
+    let imports = sqlx::query_as!(
+        ImportSummary,
+        r#"
+            SELECT
+                i.import_id,
+                i.file_name,
+                u.username,
+                l.name AS list_name,
+                i.mode,
+                i.imported_count,
+                i.skipped_count,
+                i.rejected_count,
+                i.created_at,
+                i.completed_at
+            FROM subscriber_imports i
+            JOIN users u USING (user_id)
+            JOIN lists l USING (list_id)
+            ORDER BY i.created_at DESC
+            LIMIT $1
+        "#,
+        IMPORTS_LENGTH
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the imports")?;
+    Ok(imports)
+}
@@ -0,0 +1,204 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e400, e500, flash_messages_content, render_page};
+
+/// Number of subscribers listed per page
+const SUBSCRIBERS_PER_PAGE: i64 = 50;
+
+/// Statuses of `subscriptions`, offered by the status filter
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    // Part of the email or of the name, case insensitive
+    #[serde(default)]
+    q: String,
+    // Every status when empty
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    order: SubscribersOrder,
+    // Cursor of the last subscriber of the previous page, see `PageCursor`
+    after: Option<String>,
+}
+
+/// Sort order of `subscribed_at`
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SubscribersOrder {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+/// Keyset pagination: the page starts right after this subscriber in the sort order,
+/// written as `<subscribed_at>_<id>` in the links
+struct PageCursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl PageCursor {
+    fn parse(cursor: &str) -> Result<Self, String> {
+        let (subscribed_at, id) = cursor
+            .rsplit_once('_')
+            .ok_or_else(|| format!("{} is not a valid cursor", cursor))?;
+        let subscribed_at = DateTime::parse_from_rfc3339(subscribed_at)
+            .map_err(|e| format!("{} is not a valid cursor: {}", cursor, e))?
+            .with_timezone(&Utc);
+        let id = Uuid::parse_str(id).map_err(|e| format!("{} is not a valid cursor: {}", cursor, e))?;
+        Ok(Self { subscribed_at, id })
+    }
+
+    fn of(subscriber: &SubscriberRow) -> String {
+        // Microseconds, the precision of `timestamptz`
+        format!(
+            "{}_{}",
+            subscriber.subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            subscriber.id
+        )
+    }
+}
+
+/// Every subscriber, searchable by email or name and filtered by status
+pub async fn subscribers_page(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let SubscribersQuery { q, status, order, after } = query.0;
+    let q = q.trim();
+    let status = match status.trim() {
+        "" => None,
+        status if STATUSES.contains(&status) => Some(status),
+        status => return Err(e400(format!("{} is not a valid status", status))),
+    };
+    let after = after.as_deref().map(PageCursor::parse).transpose().map_err(e400)?;
+    // One more row than displayed, to know whether there is a next page
+    let mut subscribers = get_subscribers(
+        &pool,
+        q,
+        status,
+        order,
+        after.as_ref(),
+        SUBSCRIBERS_PER_PAGE + 1
+    )
+        .await
+        .map_err(e500)?;
+    let has_next_page = subscribers.len() as i64 > SUBSCRIBERS_PER_PAGE;
+    subscribers.truncate(SUBSCRIBERS_PER_PAGE as usize);
+
+    // The filters are carried over by the pagination links
+    let first_page = format!(
+        "/admin/subscribers?q={}&status={}&order={}",
+        urlencoding::encode(q),
+        status.unwrap_or_default(),
+        match order {
+            SubscribersOrder::Newest => "newest",
+            SubscribersOrder::Oldest => "oldest",
+        }
+    );
+    let next_page = subscribers
+        .last()
+        .filter(|_| has_next_page)
+        .map(|last| format!("{}&after={}", first_page, urlencoding::encode(&PageCursor::of(last))));
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("subscribers", &subscribers);
+    context.insert("q", q);
+    context.insert("status", &status.unwrap_or_default());
+    context.insert("statuses", &STATUSES);
+    context.insert("order", &order);
+    context.insert("is_first_page", &after.is_none());
+    context.insert("first_page", &first_page);
+    context.insert("next_page", &next_page);
+    render_page(&templates, "admin/subscribers.html", &context)
+}
+
+/// `%` and `_` of the search are matched literally
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[tracing::instrument(
+    name = "Get subscribers",
+    skip(pool, after)
+)]
+async fn get_subscribers(
+    pool: &PgPool,
+    search: &str,
+    status: Option<&str>,
+    order: SubscribersOrder,
+    after: Option<&PageCursor>,
+    limit: i64,
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let pattern = (!search.is_empty()).then(|| like_pattern(search));
+    let after_subscribed_at = after.map(|a| a.subscribed_at);
+    let after_id = after.map(|a| a.id);
+    // Same query in both orders, the direction of `ORDER BY` cannot be bound
+    let subscribers = match order {
+        SubscribersOrder::Newest => sqlx::query_as!(
+            SubscriberRow,
+            r#"
+                SELECT id, email, name, status, subscribed_at, tags
+                FROM subscriptions
+                WHERE
+                    ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+                    ($2::text IS NULL OR status = $2) AND
+                    ($3::timestamptz IS NULL OR (subscribed_at, id) < ($3, $4::uuid))
+                ORDER BY subscribed_at DESC, id DESC
+                LIMIT $5
+            "#,
+            pattern,
+            status,
+            after_subscribed_at,
+            after_id,
+            limit
+        )
+            .fetch_all(pool)
+            .await,
+        SubscribersOrder::Oldest => sqlx::query_as!(
+            SubscriberRow,
+            r#"
+                SELECT id, email, name, status, subscribed_at, tags
+                FROM subscriptions
+                WHERE
+                    ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) AND
+                    ($2::text IS NULL OR status = $2) AND
+                    ($3::timestamptz IS NULL OR (subscribed_at, id) > ($3, $4::uuid))
+                ORDER BY subscribed_at, id
+                LIMIT $5
+            "#,
+            pattern,
+            status,
+            after_subscribed_at,
+            after_id,
+            limit
+        )
+            .fetch_all(pool)
+            .await,
+    }
+        .context("Failed to fetch the subscribers")?;
+    Ok(subscribers)
+}
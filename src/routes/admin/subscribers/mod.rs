@@ -0,0 +1,13 @@
+mod actions;
+mod attributes;
+mod audit;
+mod detail;
+mod import;
+mod list;
+
+pub use actions::*;
+pub use attributes::*;
+pub use audit::*;
+pub use detail::*;
+pub use import::*;
+pub use list::*;
@@ -0,0 +1,224 @@
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use actix_web::http::header::{EntityTag, ETag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use crate::domain::merge_tags::{render_merge_tags, MergeFormat, MergeValues};
+use crate::startup::{ApplicationBaseUrl, FeedDetails};
+use crate::utils::e500;
+
+/// Number of issues in the feeds, most recent first
+const FEED_LENGTH: i64 = 20;
+
+struct FeedEntry {
+    slug: String,
+    subject: String,
+    html_content: Option<String>,
+    text_content: String,
+    published_at: DateTime<Utc>,
+}
+
+impl FeedEntry {
+    fn url(&self, base_url: &str) -> String {
+        format!("{}/issues/{}", base_url, self.slug)
+    }
+
+    fn title(&self) -> String {
+        render_merge_tags(&self.subject, &MergeValues::default(), MergeFormat::Text)
+    }
+
+    /// Same rendering as the web version of the issue, escaped again by the caller
+    /// to be embedded in the XML document
+    fn content_html(&self) -> String {
+        let values = MergeValues::default();
+        match &self.html_content {
+            Some(html_content) => render_merge_tags(html_content, &values, MergeFormat::Html),
+            None => format!(
+                "<pre>{}</pre>",
+                htmlescape::encode_minimal(&render_merge_tags(&self.text_content, &values, MergeFormat::Text))
+            ),
+        }
+    }
+}
+
+/// RSS 2.0 feed of the published issues
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    feed_details: web::Data<FeedDetails>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+
+    let mut items_xml = String::new();
+    for e in &entries {
+        let url = htmlescape::encode_minimal(&e.url(base_url));
+        writeln!(
+            items_xml,
+            r#"<item>
+            <title>{title}</title>
+            <link>{url}</link>
+            <guid isPermaLink="true">{url}</guid>
+            <pubDate>{pub_date}</pubDate>
+            <description>{description}</description>
+            </item>"#,
+            title = htmlescape::encode_minimal(&e.title()),
+            pub_date = e.published_at.to_rfc2822(),
+            description = htmlescape::encode_minimal(&e.content_html()),
+        ).unwrap();
+    }
+    let last_build_date = entries
+        .first()
+        .map(|e| format!("<lastBuildDate>{}</lastBuildDate>", e.published_at.to_rfc2822()))
+        .unwrap_or_default();
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+        <channel>
+        <title>{title}</title>
+        <link>{base_url}/issues</link>
+        <description>{description}</description>
+        <atom:link href="{base_url}/feed.rss" rel="self" type="application/rss+xml"/>
+        {last_build_date}
+        {items_xml}
+        </channel>
+        </rss>
+        "#,
+        title = htmlescape::encode_minimal(&feed_details.title),
+        description = htmlescape::encode_minimal(&feed_details.description),
+        base_url = htmlescape::encode_minimal(base_url),
+    );
+    Ok(feed_response(&request, "application/rss+xml; charset=utf-8", body, &entries))
+}
+
+/// Atom feed of the published issues
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    feed_details: web::Data<FeedDetails>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+
+    let mut entries_xml = String::new();
+    for e in &entries {
+        writeln!(
+            entries_xml,
+            r#"<entry>
+            <title>{title}</title>
+            <link href="{url}"/>
+            <id>{url}</id>
+            <updated>{updated}</updated>
+            <content type="html">{content}</content>
+            </entry>"#,
+            title = htmlescape::encode_minimal(&e.title()),
+            url = htmlescape::encode_minimal(&e.url(base_url)),
+            updated = e.published_at.to_rfc3339(),
+            content = htmlescape::encode_minimal(&e.content_html()),
+        ).unwrap();
+    }
+    // `updated` is required, an empty feed has never been updated
+    let updated = entries
+        .first()
+        .map(|e| e.published_at)
+        .unwrap_or_else(|| DateTime::<Utc>::from(SystemTime::UNIX_EPOCH));
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <feed xmlns="http://www.w3.org/2005/Atom">
+        <title>{title}</title>
+        <subtitle>{description}</subtitle>
+        <link href="{base_url}/feed.atom" rel="self"/>
+        <link href="{base_url}/issues"/>
+        <id>{base_url}/feed.atom</id>
+        <updated>{updated}</updated>
+        <author><name>{title}</name></author>
+        {entries_xml}
+        </feed>
+        "#,
+        title = htmlescape::encode_minimal(&feed_details.title),
+        description = htmlescape::encode_minimal(&feed_details.description),
+        base_url = htmlescape::encode_minimal(base_url),
+        updated = updated.to_rfc3339(),
+    );
+    Ok(feed_response(&request, "application/atom+xml; charset=utf-8", body, &entries))
+}
+
+/// Build the response of a feed, or a `304 Not Modified` when the client copy is still fresh.
+///
+/// The `ETag` is a SHA-256 hash of the feed (stable across releases, unlike the std hashers),
+/// `Last-Modified` is the date of the latest issue.
+/// As in RFC 9110, `If-Modified-Since` is ignored when `If-None-Match` is present
+fn feed_response(
+    request: &HttpRequest,
+    content_type: &'static str,
+    body: String,
+    entries: &[FeedEntry],
+) -> HttpResponse {
+    let hash = Sha256::digest(body.as_bytes());
+    let etag = EntityTag::new_strong(hash.iter().fold(String::new(), |mut etag, byte| {
+        write!(etag, "{:02x}", byte).unwrap();
+        etag
+    }));
+    // HTTP dates have a one second precision
+    let last_modified = entries.first().map(|e| HttpDate::from(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(e.published_at.timestamp() as u64)
+    ));
+
+    let not_modified = match request.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(etags)) => etags.iter().any(|e| e.weak_eq(&etag)),
+        None => match (request.get_header::<IfModifiedSince>(), last_modified) {
+            (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+            _ => false,
+        },
+    };
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header(ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(last_modified));
+    }
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(content_type).body(body)
+    }
+}
+
+#[tracing::instrument(
+    name = "Get feed entries",
+    skip(pool)
+)]
+async fn get_feed_entries(pool: &PgPool) -> Result<Vec<FeedEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        FeedEntry,
+        r#"
+            SELECT
+                slug,
+                subject,
+                html_content,
+                text_content,
+                published_at AS "published_at!"
+            FROM newsletter_issues
+            WHERE status = 'published'
+            ORDER BY published_at DESC, newsletter_issue_id
+            LIMIT $1
+        "#,
+        FEED_LENGTH
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the published issues")?;
+    Ok(entries)
+}
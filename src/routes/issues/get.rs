@@ -0,0 +1,153 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use crate::domain::merge_tags::{render_merge_tags, MergeFormat, MergeValues};
+use crate::issue_delivery_worker::merge_issue;
+use crate::templates::Templates;
+use crate::utils::{e500, render_page};
+
+/// Number of issues listed per page of the public archive
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct PublicArchiveQuery {
+    // 1-based, the first page when missing
+    page: Option<u32>,
+}
+
+struct PublishedIssue {
+    slug: String,
+    subject: String,
+    published_at: Option<DateTime<Utc>>,
+}
+
+struct IssueContent {
+    subject: String,
+    html_content: Option<String>,
+    text_content: String,
+    published_at: Option<DateTime<Utc>>,
+}
+
+/// A row of the public archive
+#[derive(serde::Serialize)]
+struct ArchivedIssue {
+    slug: String,
+    subject: String,
+    published_at: String,
+}
+
+/// Public archive of the published issues, most recent first.
+/// Scheduled issues stay out of it until they are sent
+pub async fn issue_archive(
+    query: web::Query<PublicArchiveQuery>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    // One more row than displayed, to know whether there is a next page
+    let mut issues = get_published_issues(
+        &pool,
+        ISSUES_PER_PAGE + 1,
+        (page as i64 - 1) * ISSUES_PER_PAGE
+    )
+        .await
+        .map_err(e500)?;
+    let has_next_page = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+    let issues: Vec<_> = issues
+        .into_iter()
+        .map(|i| ArchivedIssue {
+            slug: i.slug,
+            subject: render_merge_tags(&i.subject, &MergeValues::default(), MergeFormat::Text),
+            published_at: format_published_at(i.published_at),
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("issues", &issues);
+    context.insert("page", &page);
+    context.insert("has_next_page", &has_next_page);
+    render_page(&templates, "issues/archive.html", &context)
+}
+
+/// Web version of a published issue, linked from the emails ("View in browser")
+pub async fn issue_page(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_published_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    // Not sent to anyone in particular: the tags fall back to their default values
+    let (subject, html_content, text_content) = merge_issue(
+        &issue.subject,
+        issue.html_content.as_deref(),
+        &issue.text_content,
+        &MergeValues::default()
+    );
+
+    let mut context = tera::Context::new();
+    context.insert("subject", &subject);
+    context.insert("published_at", &format_published_at(issue.published_at));
+    context.insert("html_content", &html_content);
+    context.insert("text_content", &text_content);
+    render_page(&templates, "issues/issue.html", &context)
+}
+
+fn format_published_at(published_at: Option<DateTime<Utc>>) -> String {
+    published_at.map(|p| p.format("%Y-%m-%d").to_string()).unwrap_or_default()
+}
+
+#[tracing::instrument(
+    name = "Get published issues",
+    skip(pool)
+)]
+async fn get_published_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+            SELECT slug, subject, published_at
+            FROM newsletter_issues
+            WHERE status = 'published'
+            ORDER BY published_at DESC, newsletter_issue_id
+            LIMIT $1
+            OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the published issues")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(
+    name = "Get published issue",
+    skip(pool)
+)]
+async fn get_published_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<IssueContent>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueContent,
+        r#"
+            SELECT subject, html_content, text_content, published_at
+            FROM newsletter_issues
+            WHERE slug = $1 AND status = 'published'
+        "#,
+        slug
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the issue")?;
+    Ok(issue)
+}
@@ -0,0 +1,30 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The list to subscribe to (or to publish to), the default one when `list_id` is missing.
+///
+/// `None` when the list does not exist or is archived
+#[tracing::instrument(
+    name = "Get active list",
+    skip(pool)
+)]
+pub async fn get_active_list(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let list = sqlx::query!(
+        r#"
+            SELECT list_id
+            FROM lists
+            WHERE
+                archived_at IS NULL AND
+                CASE WHEN $1::uuid IS NULL THEN is_default ELSE list_id = $1 END
+        "#,
+        list_id
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the list")?;
+    Ok(list.map(|l| l.list_id))
+}
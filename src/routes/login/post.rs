@@ -1,19 +1,16 @@
 use std::fmt::Formatter;
-use actix_web::cookie::Cookie;
 use actix_web::http::header::LOCATION;
-use actix_web::{cookie, web, HttpResponse, ResponseError};
-use actix_web::body::BoxBody;
+use actix_web::{web, HttpResponse};
 use actix_web::error::InternalError;
-use actix_web::http::StatusCode;
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use sqlx::PgPool;
 use crate::authentication::{validate_credentials, AuthError, Credentials};
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
 
 #[derive(serde::Deserialize)]
-pub struct FormData {
+pub struct LoginFormData {
     username: String,
     password: Secret<String>
 }
@@ -23,7 +20,7 @@ pub struct FormData {
     skip(form, pool, session),
 )]
 pub async fn login(
-    form: web::Form<FormData>,
+    form: web::Form<LoginFormData>,
     pool: web::Data<PgPool>,
     // changed from `Session` to `TypedSession`
     session: TypedSession,
@@ -34,11 +31,11 @@ pub async fn login(
     };
 
     tracing::Span::current()
-        .record("username", &tracing::field::display(&credentials.username));
+        .record("username", tracing::field::display(&credentials.username));
 
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) =>  {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             session.renew();
             session
                 .insert_user_id(user_id)
@@ -1,9 +1,15 @@
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod subscriptions_preferences;
+mod subscribers;
 mod home;
 mod login;
 mod newsletter;
+mod issues;
+mod feeds;
+mod lists;
 mod admin;
 
 fn error_chain_fmt(
@@ -23,7 +29,13 @@ fn error_chain_fmt(
 pub use health_check::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use subscriptions_preferences::*;
+pub use subscribers::*;
 pub use newsletter::*;
+pub use issues::*;
+pub use feeds::*;
+pub use lists::*;
 pub use home::*;
 pub use login::*;
 pub use admin::*;
\ No newline at end of file
@@ -1,30 +1,42 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Debug, Formatter};
 use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
 use anyhow::Context;
-use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version};
-use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
 use crate::authentication::{validate_credentials, AuthError, Credentials};
-use crate::domain::subscriber_email::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::routes::error_chain_fmt;
-use crate::telemetry::spawn_blocking_with_tracing;
+use crate::domain::email_body::EmailBody;
+use crate::domain::issue_slug::IssueSlug;
+use crate::domain::merge_tags::validate_merge_tags;
+use crate::domain::segment_filter::{bind_sql_values, SegmentFilter};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::{error_chain_fmt, get_active_list, segment_exists};
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     subject: String,
-    text: String,
+    // At least one of `html` and `text` is required,
+    // the text part is generated from `html` when missing
+    html: Option<String>,
+    text: Option<String>,
+    // Alternative to `html` and `text`, both parts are then rendered from it
+    markdown: Option<String>,
     category: String,
-}
-
-struct ConfirmedSubscriber {
-    email: SubscriberEmail
+    // RFC 3339 timestamp, the issue is scheduled when it is in the future
+    send_at: Option<DateTime<Utc>>,
+    // Sent to the confirmed members of the default list when missing
+    list: Option<Uuid>,
+    // Narrows the audience down to the subscribers of the list matching the segment
+    segment_id: Option<Uuid>,
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
     #[error(transparent)]
@@ -40,6 +52,9 @@ impl Debug for PublishError {
 impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse {
         match self {
+            PublishError::ValidationError(_) => {
+                HttpResponse::new(StatusCode::BAD_REQUEST)
+            },
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             },
@@ -62,13 +77,12 @@ impl ResponseError for PublishError {
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(body, pool, email_client, request),
+    skip(body, pool, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     // added new extractor HttpRequest
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
@@ -76,7 +90,7 @@ pub async fn publish_newsletter(
         .map_err(PublishError::AuthError)?;
     tracing::Span::current().record(
         "username",
-        &tracing::field::display(&credentials.username)
+        tracing::field::display(&credentials.username)
     );
     let user_id = validate_credentials(credentials, &pool)
         .await
@@ -86,75 +100,398 @@ pub async fn publish_newsletter(
         })?;
     tracing::Span::current().record(
         "user_id",
-        &tracing::field::display(&user_id)
+        tracing::field::display(&user_id)
     );
-    let subscribers = get_confirmed_subscribers(&pool).await?;
-
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.subject,
-                        &body.text,
-                        &body.category
-                    )
-                    .await
-                    // using `.with_context` instead of `.context` function
-                    .with_context(|| {
-                        // with_context us utilized due to the runtime cost of error handling
-                        // as the subscriber's email will not be static and will be only available at runtime
-                        format!("Failed to send newsletter email to subscriber: {}", subscriber.email)
-                    })?;
-            },
-            Err(error) => {
-                tracing::warn!(
-                    // Record the error chain as structured field
-                    // on log record
-                    error.cause_chain = ?error,
-                    //
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",
-                );
-            }
+    let BodyData { subject, html, text, markdown, category, send_at, list, segment_id } = body.0;
+    let content = match markdown {
+        Some(_) if html.is_some() || text.is_some() => Err(
+            "The markdown content cannot be combined with an html or a text content".to_string()
+        ),
+        Some(markdown) => EmailBody::from_markdown(&markdown),
+        None => EmailBody::parse(html, text),
+    }
+        .map_err(PublishError::ValidationError)?;
+    let list_id = get_active_list(&pool, list)
+        .await?
+        .ok_or_else(|| PublishError::ValidationError("The list cannot be published to".into()))?;
+    if let Some(segment_id) = segment_id {
+        if !segment_exists(&pool, segment_id).await? {
+            return Err(PublishError::ValidationError(format!("{} is not a segment", segment_id)));
         }
+    }
+
+    // A retried request carrying an already processed `Idempotency-Key` gets the saved
+    // response back instead of sending the whole issue a second time
+    let idempotency_key = get_idempotency_key(request.headers())
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let issue = NewIssue {
+        subject,
+        content,
+        category,
+        send_at,
+        list_id,
+        segment_id,
+        draft_id: None,
+    };
+    issue.validate_merge_tags().map_err(PublishError::ValidationError)?;
+    let response = publish_issue(
+        &pool,
+        user_id,
+        idempotency_key,
+        &issue,
+        HttpResponse::Accepted().finish()
+    ).await?;
+    Ok(response)
+}
 
+/// A validated newsletter issue, ready to be published
+pub struct NewIssue {
+    pub subject: String,
+    pub content: EmailBody,
+    pub category: String,
+    // The issue is scheduled when `send_at` is in the future
+    pub send_at: Option<DateTime<Utc>>,
+    // An active list, see `get_active_list`
+    pub list_id: Uuid,
+    // An existing segment, see `segment_exists`
+    pub segment_id: Option<Uuid>,
+    // Draft updated (or published) instead of storing a new issue
+    pub draft_id: Option<Uuid>,
+}
+
+impl NewIssue {
+    /// The merge tags of the subject and of both parts are resolved for each recipient
+    /// when the issue is sent, a typo would otherwise only show up in the subscribers' inbox
+    pub fn validate_merge_tags(&self) -> Result<(), String> {
+        validate_merge_tags(&self.subject)?;
+        if let Some(html) = self.content.html() {
+            validate_merge_tags(html)?;
+        }
+        validate_merge_tags(self.content.text())
     }
+}
+
+/// Store the issue and enqueue its deliveries, shared by `POST /newsletters`
+/// and the admin compose form (`/admin/newsletters`).
+///
+/// `response` is returned (and saved along with the idempotency key, if any) on success,
+/// a request retried with an already processed key gets the saved response back instead.
+#[tracing::instrument(
+    name = "Store and enqueue a newsletter issue",
+    skip(pool, issue, response)
+)]
+pub async fn publish_issue(
+    pool: &PgPool,
+    user_id: Uuid,
+    idempotency_key: Option<IdempotencyKey>,
+    issue: &NewIssue,
+    response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    store_issue(pool, user_id, idempotency_key, issue, false, response).await
+}
 
-    Ok(HttpResponse::Ok().finish())
+/// Store the issue as a draft, nothing is sent until it is published.
+/// Same idempotency handling as `publish_issue`
+#[tracing::instrument(
+    name = "Save a newsletter issue draft",
+    skip(pool, issue, response)
+)]
+pub async fn save_draft(
+    pool: &PgPool,
+    user_id: Uuid,
+    idempotency_key: Option<IdempotencyKey>,
+    issue: &NewIssue,
+    response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    store_issue(pool, user_id, idempotency_key, issue, true, response).await
 }
 
+async fn store_issue(
+    pool: &PgPool,
+    user_id: Uuid,
+    idempotency_key: Option<IdempotencyKey>,
+    issue: &NewIssue,
+    as_draft: bool,
+    response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (mut transaction, idempotency_key) = match idempotency_key {
+        Some(idempotency_key) => match try_processing(pool, &idempotency_key, user_id).await? {
+            NextAction::StartProcessing(transaction) => (transaction, Some(idempotency_key)),
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => {
+            let transaction = pool
+                .begin()
+                .await
+                .context("Failed to acquire Database Connection From the pool")?;
+            (transaction, None)
+        },
+    };
+
+    // The issue and its delivery tasks are stored in the same transaction,
+    // the emails are then sent in the background by the `issue_delivery_worker`.
+    // Scheduled issues are enqueued later on by the `issue_scheduler`
+    let (status, send_at, published_at) = if as_draft {
+        // Kept as entered, it only matters once the draft is published
+        ("draft", issue.send_at, None)
+    } else {
+        match issue.send_at.filter(|send_at| *send_at > Utc::now()) {
+            Some(send_at) => ("scheduled", Some(send_at), None),
+            None => ("published", None, Some(Utc::now())),
+        }
+    };
+    let details = IssueDetails {
+        author_id: user_id,
+        subject: &issue.subject,
+        content: &issue.content,
+        category: &issue.category,
+        list_id: issue.list_id,
+        segment_id: issue.segment_id,
+        status,
+        send_at,
+        published_at,
+    };
+    let issue_id = match issue.draft_id {
+        Some(draft_id) => {
+            let updated = update_draft(&mut transaction, draft_id, &details)
+                .await
+                .context("Failed to update the draft")?;
+            if !updated {
+                anyhow::bail!("{} is not a draft", draft_id);
+            }
+            draft_id
+        },
+        None => insert_newsletter_issue(&mut transaction, &details)
+            .await
+            .context("Failed to store newsletter issue details")?,
+    };
+    if status == "published" {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+
+    let response = match idempotency_key {
+        Some(idempotency_key) => {
+            save_response(transaction, &idempotency_key, user_id, response).await?
+        },
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the SQL transaction to store a newsletter issue")?;
+            response
+        },
+    };
+    Ok(response)
+}
+
+/// The `Idempotency-Key` header is optional, but must be valid when provided
+fn get_idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, anyhow::Error> {
+    match headers.get("Idempotency-Key") {
+        Some(header_value) => {
+            let idempotency_key = header_value
+                .to_str()
+                .context("The 'Idempotency-Key' header was not a valid UTF8 string")?
+                .to_owned()
+                .try_into()?;
+            Ok(Some(idempotency_key))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Columns of `newsletter_issues` set when an issue is stored or a draft updated
+struct IssueDetails<'a> {
+    author_id: Uuid,
+    subject: &'a str,
+    content: &'a EmailBody,
+    category: &'a str,
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
+    status: &'static str,
+    send_at: Option<DateTime<Utc>>,
+    published_at: Option<DateTime<Utc>>,
+}
 
 #[tracing::instrument(
-    name = "Get confirmed subscribers",
-    skip(pool),
+    name = "Insert newsletter issue",
+    skip_all
 )]
-async fn get_confirmed_subscribers(
-    pool: &PgPool
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error>{
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    details: &IssueDetails<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let slug = IssueSlug::new(details.subject, newsletter_issue_id);
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id,
+                subject,
+                html_content,
+                text_content,
+                category,
+                status,
+                send_at,
+                published_at,
+                author_id,
+                created_at,
+                slug,
+                list_id,
+                segment_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now(), $10, $11, $12)
+        "#,
+        newsletter_issue_id,
+        details.subject,
+        details.content.html(),
+        details.content.text(),
+        details.category,
+        details.status,
+        details.send_at,
+        details.published_at,
+        details.author_id,
+        slug.as_ref(),
+        details.list_id,
+        details.segment_id
+    )
+        .execute(transaction)
+        .await?;
+    Ok(newsletter_issue_id)
+}
 
-    // switched back to query!() from query_as!()
-    let confirmed_subscribers = sqlx::query!(
+/// Returns `false` when the issue is not a draft (anymore), it is then left untouched.
+/// The author becomes whoever saved (or published) the draft last
+#[tracing::instrument(
+    name = "Update newsletter issue draft",
+    skip(transaction, details)
+)]
+async fn update_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    details: &IssueDetails<'_>,
+) -> Result<bool, sqlx::Error> {
+    // The slug follows the subject, its id suffix keeps it unique
+    let slug = IssueSlug::new(details.subject, draft_id);
+    let updated = sqlx::query!(
         r#"
-            SELECT email
-            FROM subscriptions
-            WHERE status = 'confirmed'
-        "#
+            UPDATE newsletter_issues
+            SET
+                subject = $2,
+                html_content = $3,
+                text_content = $4,
+                category = $5,
+                status = $6,
+                send_at = $7,
+                published_at = $8,
+                author_id = $9,
+                slug = $10,
+                list_id = $11,
+                segment_id = $12
+            WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id,
+        details.subject,
+        details.content.html(),
+        details.content.text(),
+        details.category,
+        details.status,
+        details.send_at,
+        details.published_at,
+        details.author_id,
+        slug.as_ref(),
+        details.list_id,
+        details.segment_id
     )
-        .fetch_all(pool)
+        .execute(transaction)
         .await?
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
-            Err(err) => Err(anyhow::anyhow!(err))
-        })
-        .collect();
-
-    Ok(confirmed_subscribers)
+        .rows_affected();
+    Ok(updated == 1)
+}
+
+/// One delivery task per confirmed member of the issue's list, picked up by the `issue_delivery_worker`.
+/// Paused subscribers and those who opted out of the issue's category are left out,
+/// as are the ones outside of the issue's segment (if any)
+#[tracing::instrument(
+    name = "Enqueue delivery tasks",
+    skip(transaction)
+)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let segment = sqlx::query!(
+        r#"
+            SELECT g.filter
+            FROM newsletter_issues i
+            JOIN segments g USING (segment_id)
+            WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to fetch the segment of the issue")?;
+    let filter = segment
+        .map(|segment| serde_json::from_value::<SegmentFilter>(segment.filter))
+        .transpose()
+        .context("Failed to parse the segment filter")?;
+    // `$1` is the issue id, the placeholders of the segment condition follow
+    let (segment_condition, values) = match &filter {
+        Some(filter) => filter.to_sql(2),
+        None => ("TRUE".to_string(), Vec::new()),
+    };
+    let sql = format!(
+        r#"
+            INSERT INTO issue_delivery_queue (
+                newsletter_issue_id,
+                subscriber_email
+            )
+            SELECT i.newsletter_issue_id, s.email
+            FROM newsletter_issues i
+            JOIN list_memberships m ON m.list_id = i.list_id
+            JOIN subscriptions s ON s.id = m.subscription_id
+            WHERE
+                i.newsletter_issue_id = $1 AND
+                m.status = 'confirmed' AND
+                s.status = 'confirmed' AND
+                (s.paused_until IS NULL OR s.paused_until <= now()) AND
+                NOT EXISTS (
+                    SELECT 1 FROM subscription_category_opt_outs o
+                    WHERE o.subscription_id = s.id AND o.category = i.category
+                ) AND
+                {}
+        "#,
+        segment_condition
+    );
+    bind_sql_values(sqlx::query(&sql).bind(newsletter_issue_id), &values)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to enqueue the deliveries")?;
+    // Delivery report of the issue, updated by the `issue_delivery_worker`
+    sqlx::query!(
+        r#"
+            INSERT INTO deliveries (
+                newsletter_issue_id,
+                subscriber_email,
+                status,
+                updated_at
+            )
+            SELECT newsletter_issue_id, subscriber_email, 'queued', now()
+            FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to create the delivery reports")?;
+    Ok(())
 }
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+/// Credentials of the `Authorization: Basic` header, shared by the API endpoints
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     let header_value = headers
         .get("Authorization")
         .context("The 'Authorization' header was not found")?
@@ -0,0 +1,173 @@
+use std::fmt::{Debug, Formatter};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web::http::header::HeaderValue;
+use actix_web::http::{header, StatusCode};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{validate_credentials, AuthError};
+use crate::domain::subscriber_attributes::SubscriberAttributes;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::routes::{basic_authentication, error_chain_fmt};
+
+#[derive(serde::Deserialize)]
+pub struct AttributesBodyData {
+    email: String,
+    // Merged into the current attributes, a `null` value removes the attribute
+    #[serde(default)]
+    attributes: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    add_tags: Vec<String>,
+    #[serde(default)]
+    remove_tags: Vec<String>,
+}
+
+/// Tags and attributes of the subscriber once updated
+#[derive(serde::Serialize)]
+pub struct SubscriberAttributesResponse {
+    email: String,
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberAttributesError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber with this address")]
+    UnknownSubscriber,
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+
+impl Debug for SubscriberAttributesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberAttributesError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscriberAttributesError::ValidationError(_) => {
+                HttpResponse::new(StatusCode::BAD_REQUEST)
+            },
+            SubscriberAttributesError::UnknownSubscriber => {
+                HttpResponse::new(StatusCode::NOT_FOUND)
+            },
+            SubscriberAttributesError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            },
+            SubscriberAttributesError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="subscribers""#)
+                    .unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            },
+        }
+    }
+}
+
+struct StoredAttributes {
+    id: Uuid,
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+/// Update the tags and attributes of a subscriber, e.g. from a CRM.
+/// Unlike the subscribe form, any tag or attribute can be set
+#[tracing::instrument(
+    name = "Update subscriber attributes through the API",
+    skip(body, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn set_subscriber_attributes(
+    body: web::Json<AttributesBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, SubscriberAttributesError> {
+    let credentials = basic_authentication(request.headers())
+        .map_err(SubscriberAttributesError::AuthError)?;
+    tracing::Span::current().record(
+        "username",
+        tracing::field::display(&credentials.username)
+    );
+    let user_id = validate_credentials(credentials, &pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => SubscriberAttributesError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => SubscriberAttributesError::UnexpectedError(e.into())
+        })?;
+    tracing::Span::current().record(
+        "user_id",
+        tracing::field::display(&user_id)
+    );
+    let AttributesBodyData { email, attributes, add_tags, remove_tags } = body.0;
+    let add_tags = parse_tags(&add_tags)?;
+    let remove_tags = parse_tags(&remove_tags)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Database Connection From the pool")?;
+    let stored = sqlx::query_as!(
+        StoredAttributes,
+        r#"SELECT id, attributes, tags FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email
+    )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to fetch the subscriber")?
+        .ok_or(SubscriberAttributesError::UnknownSubscriber)?;
+
+    let mut merged = SubscriberAttributes::from_stored(stored.attributes).as_ref().clone();
+    for (key, value) in attributes {
+        if value.is_null() {
+            merged.remove(&key);
+        } else {
+            merged.insert(key, value);
+        }
+    }
+    let merged = SubscriberAttributes::parse(merged)
+        .map_err(SubscriberAttributesError::ValidationError)?
+        .into_value();
+    let mut tags: Vec<String> = stored.tags
+        .into_iter()
+        .chain(add_tags)
+        .filter(|tag| !remove_tags.contains(tag))
+        .collect();
+    tags.sort();
+    tags.dedup();
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = $2, tags = $3 WHERE id = $1"#,
+        stored.id,
+        merged,
+        &tags
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the subscriber attributes")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to update the subscriber attributes")?;
+
+    Ok(HttpResponse::Ok().json(SubscriberAttributesResponse {
+        email,
+        attributes: merged,
+        tags,
+    }))
+}
+
+fn parse_tags(tags: &[String]) -> Result<Vec<String>, SubscriberAttributesError> {
+    tags.iter()
+        .map(|tag| SubscriberTag::parse(tag).map(|tag| tag.as_ref().to_owned()))
+        .collect::<Result<_, _>>()
+        .map_err(SubscriberAttributesError::ValidationError)
+}
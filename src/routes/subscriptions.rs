@@ -1,4 +1,4 @@
-use std::fmt::Formatter;
+use std::collections::HashMap;
 use actix_web::{web, HttpResponse, ResponseError};
 use actix_web::body::BoxBody;
 use actix_web::http::StatusCode;
@@ -8,10 +8,14 @@ use rand::{thread_rng, Rng};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 use crate::domain::new_subscriber::NewSubscriber;
+use crate::domain::subscriber_attributes::SubscriberAttributes;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_tag::SubscriberTag;
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::routes::get_active_list;
+use crate::startup::{ApplicationBaseUrl, SubscribeFormFields};
+use crate::templates::Templates;
 
 // required for .context() function usage
 use anyhow::Context;
@@ -21,8 +25,19 @@ use crate::routes::error_chain_fmt;
 pub struct FormData {
     name: String,
     email: String,
+    // The default list when missing
+    list: Option<Uuid>,
+    // Hidden fields of the form, only the ones allowed by `SubscribeFormFields` are accepted:
+    // comma separated tags, and one `attribute:<key>` field per attribute
+    #[serde(default)]
+    tags: String,
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
 }
 
+/// Prefix of the name of the attribute fields, followed by the attribute key
+const ATTRIBUTE_FIELD_PREFIX: &str = "attribute:";
+
 // TryFrom does not need t o be imported explicitly, as it is in the prelude
 impl TryFrom<FormData> for NewSubscriber {
     type Error = String;
@@ -30,15 +45,27 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
+        let attributes = value.fields
+            .into_iter()
+            .filter_map(|(field, value)| {
+                field
+                    .strip_prefix(ATTRIBUTE_FIELD_PREFIX)
+                    .map(|key| (key.to_owned(), serde_json::Value::String(value)))
+            })
+            .collect();
+        let attributes = SubscriberAttributes::parse(attributes)?;
+        let tags = SubscriberTag::parse_list(&value.tags)?;
         Ok(Self { name,
-            email
+            email,
+            attributes,
+            tags
         })
     }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection, email_client, base_url),
+    skip(form, connection, email_client, templates, base_url, form_fields),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -50,25 +77,80 @@ pub async fn subscribe(
     connection: web::Data<PgPool>,
     // getting email_client instance from the application state
     email_client: web::Data<EmailClient>,
+    templates: web::Data<Templates>,
     // application server base url
-    base_url: web::Data<ApplicationBaseUrl>
+    base_url: web::Data<ApplicationBaseUrl>,
+    form_fields: web::Data<SubscribeFormFields>,
 ) -> Result<HttpResponse, SubscribeError> {
 
     // This can also be written as `NewSubscriber::try_from(form.0)`
     // The try_into(TryInto) implementation is provided for free by the `TryFrom` trait
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let list = form.list;
+    let mut new_subscriber: NewSubscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    check_form_fields(&new_subscriber, &form_fields).map_err(SubscribeError::ValidationError)?;
+    let list_id = get_active_list(&connection, list)
+        .await?
+        .ok_or_else(|| SubscribeError::ValidationError("The list cannot be subscribed to".into()))?;
 
     let mut transaction = connection
         .begin()
         .await
         .context("Failed to acquire Database Connection From the pool")?;
 
-    let subscription_id = insert_subscriber(
-        &mut transaction,
-        &new_subscriber
-    )
+    let mut existing_subscriber = get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+        .await
+        .context("Failed to look up the subscriber in the database")?;
+    let mut new_subscriber_id = None;
+    if existing_subscriber.is_none() {
+        new_subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to add new subscriber into the database")?;
+        // A concurrent subscription inserted the address since the lookup (the insert waited
+        // for it to commit): handled as a repeat subscription
+        if new_subscriber_id.is_none() {
+            existing_subscriber = get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+                .await
+                .context("Failed to look up the subscriber in the database")?;
+        }
+    }
+    // The confirmation email of an existing subscriber greets them with the stored name:
+    // anyone can submit the form with the address of someone else
+    if let Some(subscriber) = &existing_subscriber {
+        new_subscriber.name = SubscriberName::parse(subscriber.name.clone())
+            .map_err(anyhow::Error::msg)
+            .context("The stored subscriber name is not valid")?;
+    }
+    // The attributes and tags of the form are only stored along with a new subscriber:
+    // the form is public, anyone could otherwise change those of an existing address
+    let subscription_id = match (new_subscriber_id, existing_subscriber) {
+        (Some(subscriber_id), _) => subscriber_id,
+        (None, None) => {
+            return Err(anyhow::anyhow!("The subscriber was deleted during the subscription").into());
+        },
+        // Same response as a new subscription, so that the endpoint
+        // does not reveal which addresses are already subscribed.
+        // Joining another list still has to be confirmed
+        (None, Some(subscriber)) if subscriber.status == "confirmed" => {
+            if is_confirmed_member(&mut transaction, list_id, subscriber.id)
+                .await
+                .context("Failed to look up the list membership")? {
+                return Ok(HttpResponse::Ok().finish());
+            }
+            subscriber.id
+        },
+        // The confirmation email got lost (or the subscriber left and wants to come back):
+        // reuse the existing row and send a new confirmation email
+        (None, Some(subscriber)) => {
+            reset_to_pending_confirmation(&mut transaction, subscriber.id)
+                .await
+                .context("Failed to reset the subscriber to pending confirmation")?;
+            subscriber.id
+        },
+    };
+
+    add_to_list(&mut transaction, list_id, subscription_id)
         .await
-        .context("Failed to add new subscriber into the database")?;
+        .context("Failed to add the subscriber to the list")?;
 
     // Get the new generated subscription token
     let subscription_token = generate_subscription_token();
@@ -87,6 +169,7 @@ pub async fn subscribe(
 
     send_confirmation_email(
         &email_client,
+        &templates,
         new_subscriber,
         &base_url.0,
         &subscription_token // dynamic token assignment
@@ -97,37 +180,57 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Anyone can submit the form: only the attributes and tags it is meant to carry are accepted
+fn check_form_fields(new_subscriber: &NewSubscriber, allowed: &SubscribeFormFields) -> Result<(), String> {
+    if let Some(key) = new_subscriber.attributes
+        .as_ref()
+        .keys()
+        .find(|key| !allowed.attributes.contains(key)) {
+        return Err(format!("The {} attribute cannot be set by the subscribe form", key));
+    }
+    if let Some(tag) = new_subscriber.tags
+        .iter()
+        .find(|tag| !allowed.tags.iter().any(|allowed| allowed == tag.as_ref())) {
+        return Err(format!("The {} tag cannot be set by the subscribe form", tag.as_ref()));
+    }
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, templates, new_subscriber, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    templates: &Templates,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str
-) -> Result<(), reqwest::Error> {
-    // Added a static confirmation link
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url,
         subscription_token
     );
-    // Send a static email to the new subscriber
+    let mut context = tera::Context::new();
+    context.insert("name", new_subscriber.name.as_ref());
+    context.insert("confirmation_link", &confirmation_link);
+    let html_body = templates.render("emails/confirmation.html", &context)?;
+    // Plain-text clients would otherwise render the markup as is
+    let text_body = templates.render("emails/confirmation.txt", &context)?;
     email_client
         .send_email(
             &new_subscriber.email,
-            "Weclome!",
-            &format!(
-                "Welcome to our newsletter! <br/> \
-                Click <a href = \"{}\">here</a> to confirm your subscription",
-                confirmation_link
-            ),
+            "Welcome!",
+            Some(&html_body),
+            &text_body,
             "welcome mail"
         )
-        .await
+        .await?;
+    Ok(())
 }
 
+/// `None` when the address is already taken, the unique `email` column deciding
 #[tracing::instrument(
     name = "Inserting new subscriber",
     skip(new_subscriber, transaction),
@@ -136,17 +239,25 @@ pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &crate::domain::new_subscriber::NewSubscriber,
 
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    // Long-lived, used by the `List-Unsubscribe` link of every newsletter
+    let unsubscribe_token = generate_subscription_token();
+    let inserted = sqlx::query!(
         r#"
-            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-            VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO subscriptions (
+                id, email, name, subscribed_at, status, unsubscribe_token, attributes, tags
+            )
+            VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6, $7)
+            ON CONFLICT (email) DO NOTHING
         "#, // default status is kept as pending_confirmation
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        unsubscribe_token,
+        new_subscriber.attributes.clone().into_value(),
+        &new_subscriber.tags.iter().map(|tag| tag.as_ref().to_owned()).collect::<Vec<_>>()
     )
         .execute(transaction)
         .await
@@ -155,15 +266,109 @@ pub async fn insert_subscriber(
             e
             // Using the ? operator to return early and propagate the error
             // return sqlx::error
-        })?;
-    Ok(subscriber_id)
+        })?
+        .rows_affected();
+    Ok((inserted == 1).then_some(subscriber_id))
+}
+
+pub struct ExistingSubscriber {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String,
+}
+
+/// The row is locked until the end of the transaction,
+/// so that concurrent repeat subscriptions are handled one at a time
+#[tracing::instrument(
+    name = "Get subscriber by email",
+    skip(transaction, email),
+)]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, name, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref()
+    )
+        .fetch_optional(transaction)
+        .await
+}
+
+#[tracing::instrument(
+    name = "Check list membership",
+    skip(transaction),
+)]
+pub async fn is_confirmed_member(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let membership = sqlx::query!(
+        r#"
+            SELECT status
+            FROM list_memberships
+            WHERE list_id = $1 AND subscription_id = $2
+        "#,
+        list_id,
+        subscriber_id
+    )
+        .fetch_optional(transaction)
+        .await?;
+    Ok(membership.map(|m| m.status == "confirmed").unwrap_or(false))
+}
+
+/// The membership is pending until the subscriber follows the confirmation link,
+/// a confirmed membership is left as is
+#[tracing::instrument(
+    name = "Add subscriber to list",
+    skip(transaction),
+)]
+pub async fn add_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO list_memberships (list_id, subscription_id, status, created_at)
+            VALUES ($1, $2, 'pending_confirmation', now())
+            ON CONFLICT (list_id, subscription_id) DO UPDATE
+            SET status = 'pending_confirmation'
+            WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Unsubscribed subscribers have to confirm their address again
+#[tracing::instrument(
+    name = "Reset subscriber to pending confirmation",
+    skip(transaction),
+)]
+pub async fn reset_to_pending_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'pending_confirmation' WHERE id = $1"#,
+        subscriber_id
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
 }
 
 /// using `rand` package's `std_rng` feature to generate a
 /// "CryptoGraphically Secure Pseudo Number Generator" to generate subscription tokens
 ///
 /// Generate a 25-character-long case-sensitive subscription token
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -1,6 +1,10 @@
 use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use actix_web::http::header::ContentType;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
+use crate::startup::SubscriptionTokenTtl;
+use crate::templates::Templates;
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -9,22 +13,47 @@ pub struct Parameters {
 
 #[tracing::instrument(
     name = "Confirm a pending subscriber",
-    skip(_parameters),
+    skip(_parameters, db_pool, token_ttl, templates),
 )]
 pub async fn confirm(
     _parameters: web::Query<Parameters>,
-    db_pool: web::Data<PgPool>
+    db_pool: web::Data<PgPool>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    templates: web::Data<Templates>,
 ) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&db_pool, &_parameters.subscription_token).await {
-        Ok(id) => id,
+    let token = match get_subscription_token(&db_pool, &_parameters.subscription_token).await {
+        Ok(token) => token,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    match id {
+    match token {
         // If the subscription token does not exist
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscription_id) => {
-            if confirm_subscriber(&db_pool, subscription_id).await.is_err() {
+        Some(token) if token.consumed_at.is_some() => confirmation_page(
+            &templates,
+            "This confirmation link has already been used."
+        ),
+        Some(token) if token.created_at + token_ttl.0 < Utc::now() => confirmation_page(
+            &templates,
+            "This confirmation link has expired, please subscribe again to receive a new one."
+        ),
+        Some(token) => {
+            let mut transaction = match db_pool.begin().await {
+                Ok(transaction) => transaction,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            match consume_token(&mut transaction, &_parameters.subscription_token).await {
+                Ok(true) => {},
+                // The link was clicked twice concurrently, the other request won
+                Ok(false) => return confirmation_page(
+                    &templates,
+                    "This confirmation link has already been used."
+                ),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            }
+            if confirm_subscriber(&mut transaction, token.subscription_id).await.is_err()
+                || confirm_list_memberships(&mut transaction, token.subscription_id).await.is_err()
+                || transaction.commit().await.is_err() {
                 return HttpResponse::InternalServerError().finish()
             }
             HttpResponse::Ok().finish()
@@ -32,19 +61,30 @@ pub async fn confirm(
     }
 }
 
+/// `410 Gone` page explaining why an expired or already used link was rejected
+fn confirmation_page(templates: &Templates, message: &str) -> HttpResponse {
+    let mut context = tera::Context::new();
+    context.insert("message", message);
+    match templates.render("subscriptions/confirm.html", &context) {
+        Ok(body) => HttpResponse::Gone().content_type(ContentType::html()).body(body),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[tracing::instrument(
     name="Mark subscriber as confirmed in database",
-    skip(db_pool, subscription_id),
+    skip(transaction, subscription_id),
 )]
 pub async fn confirm_subscriber(
-    db_pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        // An old confirmation link must not bring back a subscriber who has unsubscribed
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status = 'pending_confirmation'"#,
         subscription_id
     )
-        .execute(db_pool)
+        .execute(transaction)
         .await
         .map_err(|e|{
             tracing::error!("Failed to execute `confirm_subscriber` query: {:?}", e);
@@ -53,25 +93,88 @@ pub async fn confirm_subscriber(
     Ok(())
 }
 
+/// The lists joined since the last confirmation, provided the subscriber is (still) confirmed
 #[tracing::instrument(
-    name="Get subscriber id from token",
+    name="Mark list memberships as confirmed in database",
+    skip(transaction, subscription_id),
+)]
+pub async fn confirm_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE list_memberships
+            SET status = 'confirmed'
+            WHERE
+                subscription_id = $1 AND
+                status = 'pending_confirmation' AND
+                EXISTS (SELECT 1 FROM subscriptions WHERE id = $1 AND status = 'confirmed')
+        "#,
+        subscription_id
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e|{
+            tracing::error!("Failed to execute `confirm_list_memberships` query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+/// Returns `false` if the token had already been consumed
+#[tracing::instrument(
+    name="Mark subscription token as consumed",
+    skip(transaction, subscription_token),
+)]
+pub async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE subscription_tokens
+            SET consumed_at = now()
+            WHERE subscription_token = $1 AND consumed_at IS NULL
+        "#,
+        subscription_token
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e|{
+            tracing::error!("Failed to execute `consume_token` query: {:?}", e);
+            e
+        })?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub struct SubscriptionToken {
+    subscription_id: Uuid,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name="Get subscription token",
     skip(db_pool)
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_token(
     db_pool: &PgPool,
     subscription_token: &str
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let subscription_id = sqlx::query!(
-        r#"SELECT subscription_id from subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+            SELECT subscription_id, created_at, consumed_at
+            FROM subscription_tokens
+            WHERE subscription_token = $1
+        "#,
         subscription_token
     )
         .fetch_optional(db_pool)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to fetch `subscription_token` from `get_subscriber_id_from_token`: {:?}", e);
+            tracing::error!("Failed to fetch `subscription_token` from `get_subscription_token`: {:?}", e);
             e
-        })?;
-
-    // Optionally return if the subscription token is found in the database
-    Ok(subscription_id.map(|r| r.subscription_id))
-}
\ No newline at end of file
+        })
+}
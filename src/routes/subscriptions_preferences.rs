@@ -0,0 +1,287 @@
+use std::collections::{BTreeSet, HashMap};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::templates::Templates;
+use crate::utils::{e500, flash_messages_content, render_page, see_other};
+
+/// Longest pause a subscriber can ask for
+const MAX_PAUSE_WEEKS: i64 = 52;
+
+/// Prefix of the name of the category checkboxes, followed by the category
+const CATEGORY_FIELD_PREFIX: &str = "category:";
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    // The subscriber's unsubscribe token, found in every newsletter
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    token: String,
+    name: String,
+    // Empty to leave the current pause (if any) as is, `0` to resume right away
+    #[serde(default)]
+    pause_weeks: String,
+    // One `category:<category>` field per checked category
+    #[serde(flatten)]
+    categories: HashMap<String, String>,
+}
+
+struct Subscriber {
+    id: Uuid,
+    name: String,
+    status: String,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct CategoryPreference {
+    category: String,
+    received: bool,
+}
+
+/// Preference center linked from every newsletter: the subscriber's name,
+/// the categories received and a pause of the deliveries
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = match get_subscriber(&pool, &parameters.token).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let opt_outs = get_category_opt_outs(&pool, subscriber.id).await.map_err(e500)?;
+    let categories: Vec<_> = get_categories(&pool, subscriber.id)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|category| CategoryPreference {
+            received: !opt_outs.contains(&category),
+            category,
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &flash_messages_content(&flash_messages));
+    context.insert("token", &parameters.token);
+    context.insert("name", &subscriber.name);
+    context.insert("confirmed", &(subscriber.status == "confirmed"));
+    context.insert("paused_until", &subscriber.paused_until.filter(|p| *p > Utc::now()));
+    context.insert("categories", &categories);
+    context.insert("category_field_prefix", CATEGORY_FIELD_PREFIX);
+    context.insert("max_pause_weeks", &MAX_PAUSE_WEEKS);
+    render_page(&templates, "subscriptions/preferences.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip(form, pool),
+)]
+pub async fn update_preferences(
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let PreferencesFormData { token, name, pause_weeks, categories } = form.0;
+    let preferences_page = format!(
+        "/subscriptions/preferences?token={}",
+        urlencoding::encode(&token)
+    );
+    let subscriber = match get_subscriber(&pool, &token).await.map_err(e500)? {
+        Some(subscriber) if subscriber.status == "confirmed" => subscriber,
+        Some(_) => {
+            FlashMessage::error("You are not subscribed anymore.").send();
+            return Ok(see_other(&preferences_page));
+        },
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+
+    let name = match SubscriberName::parse(name.trim().to_owned()) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(format!("{}.", e)).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+    let paused_until = match parse_pause(&pause_weeks) {
+        Ok(pause) => pause,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+    let received: BTreeSet<&str> = categories
+        .keys()
+        .filter_map(|field| field.strip_prefix(CATEGORY_FIELD_PREFIX))
+        .collect();
+    let opt_outs: Vec<String> = get_categories(&pool, subscriber.id)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .filter(|category| !received.contains(category.as_str()))
+        .collect();
+
+    save_preferences(&pool, subscriber.id, &name, paused_until, &opt_outs)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_page))
+}
+
+/// `None` to leave the pause as is, `Some(None)` to resume the deliveries
+fn parse_pause(pause_weeks: &str) -> Result<Option<Option<DateTime<Utc>>>, String> {
+    let pause_weeks = pause_weeks.trim();
+    if pause_weeks.is_empty() {
+        return Ok(None);
+    }
+    match pause_weeks.parse::<i64>() {
+        Ok(0) => Ok(Some(None)),
+        Ok(weeks) if (1..=MAX_PAUSE_WEEKS).contains(&weeks) => {
+            Ok(Some(Some(Utc::now() + Duration::weeks(weeks))))
+        },
+        _ => Err(format!("The pause must be between 0 and {} weeks.", MAX_PAUSE_WEEKS)),
+    }
+}
+
+#[tracing::instrument(
+    name = "Get subscriber by unsubscribe token",
+    skip(pool, token)
+)]
+async fn get_subscriber(pool: &PgPool, token: &str) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+            SELECT id, name, status, paused_until
+            FROM subscriptions
+            WHERE unsubscribe_token = $1
+        "#,
+        token
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch the subscriber")?;
+    Ok(subscriber)
+}
+
+/// The categories of the issues published so far to the lists of the subscriber,
+/// along with the ones the subscriber opted out of
+#[tracing::instrument(
+    name = "Get newsletter categories",
+    skip(pool)
+)]
+async fn get_categories(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<String>, anyhow::Error> {
+    let categories = sqlx::query!(
+        r#"
+            SELECT i.category AS "category!"
+            FROM newsletter_issues i
+            JOIN list_memberships m ON m.list_id = i.list_id
+            WHERE i.status = 'published' AND m.subscription_id = $1
+            UNION
+            SELECT category
+            FROM subscription_category_opt_outs
+            WHERE subscription_id = $1
+            ORDER BY 1
+        "#,
+        subscriber_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the categories")?;
+    Ok(categories.into_iter().map(|c| c.category).collect())
+}
+
+#[tracing::instrument(
+    name = "Get category opt-outs",
+    skip(pool)
+)]
+async fn get_category_opt_outs(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<BTreeSet<String>, anyhow::Error> {
+    let opt_outs = sqlx::query!(
+        r#"
+            SELECT category
+            FROM subscription_category_opt_outs
+            WHERE subscription_id = $1
+        "#,
+        subscriber_id
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to fetch the category opt-outs")?;
+    Ok(opt_outs.into_iter().map(|o| o.category).collect())
+}
+
+#[tracing::instrument(
+    name = "Save subscriber preferences",
+    skip(pool, name)
+)]
+async fn save_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+    paused_until: Option<Option<DateTime<Utc>>>,
+    opt_outs: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Database Connection From the pool")?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref()
+    )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update the subscriber name")?;
+    if let Some(paused_until) = paused_until {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET paused_until = $2 WHERE id = $1"#,
+            subscriber_id,
+            paused_until
+        )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to update the pause")?;
+    }
+    replace_category_opt_outs(&mut transaction, subscriber_id, opt_outs)
+        .await
+        .context("Failed to update the category opt-outs")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to save the preferences")?;
+    Ok(())
+}
+
+async fn replace_category_opt_outs(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    opt_outs: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_category_opt_outs WHERE subscription_id = $1"#,
+        subscriber_id
+    )
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO subscription_category_opt_outs (subscription_id, category)
+            SELECT $1, category FROM UNNEST($2::text[]) AS category
+        "#,
+        subscriber_id,
+        opt_outs
+    )
+        .execute(&mut *transaction)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::templates::Templates;
+use crate::utils::{e500, render_page};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String
+}
+
+/// Landing page of the unsubscribe link found in every newsletter: asks for a confirmation,
+/// mail scanners and link prefetchers following the link must not unsubscribe anyone
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_known_token(&db_pool, &parameters.unsubscribe_token).await.map_err(e500)? {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let unsubscribe_url = format!(
+        "/subscriptions/unsubscribe?unsubscribe_token={}",
+        urlencoding::encode(&parameters.unsubscribe_token)
+    );
+    let mut context = tera::Context::new();
+    context.insert("unsubscribed", &false);
+    context.insert("unsubscribe_url", &unsubscribe_url);
+    render_page(&templates, "subscriptions/unsubscribe.html", &context)
+}
+
+/// Submitted by the form of the landing page, and one-click unsubscribe (RFC 8058):
+/// mail clients `POST` to the `List-Unsubscribe` url with a `List-Unsubscribe=One-Click` body,
+/// the token is still carried by the query string
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, db_pool, templates),
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !unsubscribe_subscriber(&db_pool, &parameters.unsubscribe_token).await.map_err(e500)? {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let mut context = tera::Context::new();
+    context.insert("unsubscribed", &true);
+    render_page(&templates, "subscriptions/unsubscribe.html", &context)
+}
+
+#[tracing::instrument(
+    name = "Look up unsubscribe token",
+    skip(db_pool, unsubscribe_token),
+)]
+async fn is_known_token(db_pool: &PgPool, unsubscribe_token: &str) -> Result<bool, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE unsubscribe_token = $1"#,
+        unsubscribe_token
+    )
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(subscriber.is_some())
+}
+
+/// Returns `false` if the token does not belong to any subscriber.
+///
+/// Unsubscribing twice is not an error, the subscriber simply stays `unsubscribed`.
+/// The subscriber leaves every list: subscribing again only brings back the list subscribed to
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed in database",
+    skip(db_pool, unsubscribe_token),
+)]
+pub async fn unsubscribe_subscriber(
+    db_pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
+    let subscriber = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = 'unsubscribed'
+            WHERE unsubscribe_token = $1
+            RETURNING id
+        "#,
+        unsubscribe_token
+    )
+        .fetch_optional(&mut transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute `unsubscribe_subscriber` query: {:?}", e);
+            e
+        })?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(false),
+    };
+    leave_every_list(&mut transaction, subscriber.id).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Part of every unsubscription, by the subscriber or by an admin
+#[tracing::instrument(
+    name = "Mark list memberships as unsubscribed in database",
+    skip(transaction),
+)]
+pub async fn leave_every_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscription_id = $1"#,
+        subscriber_id
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
@@ -12,10 +12,10 @@ use sqlx::{PgPool};
 use sqlx::postgres::PgPoolOptions;
 use tracing_actix_web::TracingLogger;
 use crate::authentication::reject_anonymous_users;
-use crate::configuration::{get_configuration, DatabaseSettings, Settings};
+use crate::configuration::{DatabaseSettings, Settings};
 use crate::email_client::EmailClient;
-use crate::routes::{admin_dashboard, change_password, change_password_form, confirm, health_check, home, login, login_form, logout, publish_newsletter, subscribe};
-use crate::telemetry::{get_subscriber, init_subscriber};
+use crate::templates::Templates;
+use crate::routes::{admin_dashboard, archive_list, atom_feed, cancel_scheduled_newsletter, change_password, change_password_form, confirm, confirm_subscriber_manually, create_list, create_segment, dead_letters, delete_subscriber, health_check, home, import_results, import_subscribers, import_subscribers_form, issue_archive, issue_page, issue_preview, issue_report, list_issues, lists_page, login, login_form, logout, preferences_form, preview_segment, publish_newsletter, publish_newsletter_form, publish_newsletter_from_form, rename_list, rename_subscriber, requeue_dead_letter, resend_confirmation, rss_feed, scheduled_newsletters, segments_page, send_test_issue, set_subscriber_attributes, subscribe, subscriber_attributes_page, subscriber_audit_page, subscriber_page, subscribers_page, unsubscribe, unsubscribe_form, unsubscribe_subscriber_manually, update_preferences, update_subscriber_attributes};
 
 /// A new type for the application server
 /// wrap actix_web::dev::Server
@@ -33,35 +33,36 @@ impl Application {
     ) -> Result<Self, anyhow::Error> {
         // Moved te startup initialization logic to a separate function
 
-        // Using Pool implementation in order to handle concurrency of database query executions
-        // only try to establish a connection when the pool is used for the first time.
-        let connection = PgPoolOptions::new()
-            .connect_timeout(std::time::Duration::from_secs(2))
-            .connect_lazy_with(configuration.database.with_db());
+        let connection = get_connection_pool(&configuration.database);
 
         // A new `EmailClient` created using `configuration`
-        let sender_email = configuration.email_client.sender()
-            .expect("Invalid Sender Email Address");
-        let sender_name = configuration.email_client.sender_name()
-            .expect("Invalid Sender Name");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            sender_name,
-            configuration.email_client.authorization_token,
-            timeout
-        );
+        let email_client = configuration.email_client.client();
+
+        // Invalid templates fail the startup rather than the requests rendering them
+        let templates = Templates::load()?;
 
         // Remove the hardcoded 9001 port
         let address = format!("{}:{}", configuration.application.host , configuration.application.port);
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr()?.port();
         // Storing the actix::Server object
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
+        let feed_details = FeedDetails {
+            title: configuration.application.feed_title,
+            description: configuration.application.feed_description,
+        };
+        let subscribe_form_fields = SubscribeFormFields {
+            attributes: configuration.application.subscribe_form_attributes,
+            tags: configuration.application.subscribe_form_tags,
+        };
         let server = run(
             listener,
             connection,
             email_client,
+            templates,
+            subscription_token_ttl,
+            feed_details,
+            subscribe_form_fields,
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri
@@ -102,11 +103,31 @@ pub fn get_connection_pool(
 // a raw `String` would expose us to conflicts.
 pub struct ApplicationBaseUrl(pub String);
 
+/// Validity of the subscription confirmation tokens, used by the `confirm` handler
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+/// Title and description of the feeds of the published issues
+pub struct FeedDetails {
+    pub title: String,
+    pub description: String,
+}
+
+/// Attributes and tags the subscribe form is allowed to set, anything else is rejected
+pub struct SubscribeFormFields {
+    pub attributes: Vec<String>,
+    pub tags: Vec<String>,
+}
+
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    templates: Templates,
+    subscription_token_ttl: chrono::Duration,
+    feed_details: FeedDetails,
+    subscribe_form_fields: SubscribeFormFields,
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>
@@ -119,8 +140,12 @@ pub async fn run(
 
     // Wrap the email client in web::Data to share it across requests
     let email_client = web::Data::new(email_client);
+    let templates = web::Data::new(templates);
 
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let subscription_token_ttl = Data::new(SubscriptionTokenTtl(subscription_token_ttl));
+    let feed_details = Data::new(feed_details);
+    let subscribe_form_fields = Data::new(subscribe_form_fields);
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
 
@@ -137,13 +162,52 @@ pub async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/preferences", web::get().to(preferences_form))
+            .route("/subscriptions/preferences", web::post().to(update_preferences))
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/subscribers/attributes", web::post().to(set_subscriber_attributes))
+            .route("/issues", web::get().to(issue_archive))
+            .route("/issues/{slug}", web::get().to(issue_page))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
             .service(
                 web::scope("/admin")
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(logout))
+                    .route("/deliveries/failed", web::get().to(dead_letters))
+                    .route("/deliveries/failed/requeue", web::post().to(requeue_dead_letter))
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues/{newsletter_issue_id}", web::get().to(issue_report))
+                    .route("/issues/{newsletter_issue_id}/preview", web::get().to(issue_preview))
+                    .route("/issues/{newsletter_issue_id}/test", web::post().to(send_test_issue))
+                    .route("/lists", web::get().to(lists_page))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/lists/{list_id}/rename", web::post().to(rename_list))
+                    .route("/lists/{list_id}/archive", web::post().to(archive_list))
+                    .route("/segments", web::get().to(segments_page))
+                    .route("/segments", web::post().to(create_segment))
+                    .route("/segments/preview", web::post().to(preview_segment))
+                    .route("/subscribers/attributes", web::get().to(subscriber_attributes_page))
+                    .route("/subscribers/attributes", web::post().to(update_subscriber_attributes))
+                    .route("/subscribers", web::get().to(subscribers_page))
+                    .route("/subscribers/audit", web::get().to(subscriber_audit_page))
+                    .route("/subscribers/import", web::get().to(import_subscribers_form))
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/import/{import_id}/results", web::get().to(import_results))
+                    .route("/subscribers/{subscriber_id}", web::get().to(subscriber_page))
+                    .route("/subscribers/{subscriber_id}/confirm", web::post().to(confirm_subscriber_manually))
+                    .route("/subscribers/{subscriber_id}/unsubscribe", web::post().to(unsubscribe_subscriber_manually))
+                    .route("/subscribers/{subscriber_id}/resend_confirmation", web::post().to(resend_confirmation))
+                    .route("/subscribers/{subscriber_id}/rename", web::post().to(rename_subscriber))
+                    .route("/subscribers/{subscriber_id}/delete", web::post().to(delete_subscriber))
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route("/newsletters", web::post().to(publish_newsletter_from_form))
+                    .route("/newsletters/scheduled", web::get().to(scheduled_newsletters))
+                    .route("/newsletters/scheduled/cancel", web::post().to(cancel_scheduled_newsletter))
                     .wrap(actix_web_lab::middleware::from_fn(reject_anonymous_users))
             )
             .wrap(message_framework.clone())
@@ -152,8 +216,12 @@ pub async fn run(
             .wrap(TracingLogger::default())
             // Added email_client to application state/context
             .app_data(email_client.clone())
+            .app_data(templates.clone())
             .app_data(connection.clone())
             .app_data(base_url.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(feed_details.clone())
+            .app_data(subscribe_form_fields.clone())
             // added hmac_secret for application context
             // .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
@@ -0,0 +1,80 @@
+use std::time::Duration;
+use chrono::Utc;
+use sqlx::PgPool;
+use crate::configuration::Settings;
+use crate::startup::get_connection_pool;
+
+/// How often the stale tokens and subscriptions are purged
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PurgeOutcome {
+    pub purged_tokens: u64,
+    pub purged_subscriptions: u64,
+}
+
+/// Background task purging the expired confirmation tokens and the subscriptions
+/// that were never confirmed, runs alongside the API server (see `main.rs`)
+pub async fn run_cleanup_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let token_ttl = configuration.application.subscription_token_ttl();
+    cleanup_loop(connection_pool, token_ttl).await
+}
+
+async fn cleanup_loop(
+    pool: PgPool,
+    token_ttl: chrono::Duration,
+) -> Result<(), anyhow::Error> {
+    loop {
+        // Failures are logged by `purge_stale_subscriptions`, the next run will catch up
+        let _ = purge_stale_subscriptions(&pool, token_ttl).await;
+        tokio::time::sleep(CLEANUP_INTERVAL).await;
+    }
+}
+
+/// Delete the unused tokens older than `token_ttl`, then the subscribers still pending confirmation
+/// who are left without any token.
+///
+/// The consumed tokens are kept, as the confirmation history shown on the subscriber pages
+/// (a subscriber who confirmed once is then never purged)
+#[tracing::instrument(skip(pool), err)]
+pub async fn purge_stale_subscriptions(
+    pool: &PgPool,
+    token_ttl: chrono::Duration,
+) -> Result<PurgeOutcome, anyhow::Error> {
+    let expired_before = Utc::now() - token_ttl;
+    let mut transaction = pool.begin().await?;
+    let purged_tokens = sqlx::query!(
+        r#"
+            DELETE FROM subscription_tokens
+            WHERE consumed_at IS NULL AND created_at < $1
+        "#,
+        expired_before
+    )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    let purged_subscriptions = sqlx::query!(
+        r#"
+            DELETE FROM subscriptions
+            WHERE
+                status = 'pending_confirmation' AND
+                subscribed_at < $1 AND
+                NOT EXISTS (
+                    SELECT 1 FROM subscription_tokens
+                    WHERE subscription_tokens.subscription_id = subscriptions.id
+                )
+        "#,
+        expired_before
+    )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    transaction.commit().await?;
+
+    tracing::info!(purged_tokens, purged_subscriptions, "Purged stale subscriptions");
+    Ok(PurgeOutcome {
+        purged_tokens,
+        purged_subscriptions,
+    })
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+use anyhow::Context;
+use tera::Tera;
+
+/// Templates rendered by the application, their absence fails the startup
+/// rather than the first request (or email) needing them
+const REQUIRED_TEMPLATES: [&str; 24] = [
+    "admin/dashboard.html",
+    "admin/password.html",
+    "admin/dead_letters.html",
+    "admin/newsletter_form.html",
+    "admin/scheduled_newsletters.html",
+    "admin/issues.html",
+    "admin/issue_report.html",
+    "admin/issue_preview.html",
+    "admin/lists.html",
+    "admin/segments.html",
+    "admin/subscriber_attributes.html",
+    "admin/subscribers.html",
+    "admin/subscriber.html",
+    "admin/subscriber_audit.html",
+    "admin/subscriber_import.html",
+    "emails/confirmation.html",
+    "emails/confirmation.txt",
+    "emails/newsletter.html",
+    "emails/newsletter.txt",
+    "issues/archive.html",
+    "issues/issue.html",
+    "subscriptions/confirm.html",
+    "subscriptions/preferences.html",
+    "subscriptions/unsubscribe.html",
+];
+
+/// Templates of the admin pages and of the emails, loaded from the `templates` directory.
+///
+/// Variables of the `.html` templates are escaped, the `.txt` ones are rendered as is.
+#[derive(Clone, Debug)]
+pub struct Templates(Tera);
+
+impl Templates {
+    /// Load the `templates` directory, located next to `configuration`
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let base_path = std::env::current_dir().context("Failed to retrieve current directory")?;
+        Self::from_directory(&base_path.join("templates"))
+    }
+
+    /// Fails on a syntax error, on a missing parent template (`{% extends %}`)
+    /// or when one of the required templates is missing
+    pub fn from_directory(directory: &Path) -> Result<Self, anyhow::Error> {
+        let glob = directory.join("**").join("*");
+        let glob = glob.to_str().context("The templates directory is not a valid UTF8 path")?;
+        let mut tera = Tera::new(glob)
+            .with_context(|| format!("Failed to load the templates from {}", directory.display()))?;
+        tera.autoescape_on(vec![".html"]);
+        // Same escaping as the rest of the application, `/` in urls is left as is
+        tera.set_escape_fn(htmlescape::encode_minimal);
+
+        let missing: Vec<_> = REQUIRED_TEMPLATES
+            .iter()
+            .filter(|name| !tera.get_template_names().any(|n| n == **name))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Missing templates in {}: {:?}", directory.display(), missing);
+        }
+        Ok(Self(tera))
+    }
+
+    pub fn render(&self, name: &str, context: &tera::Context) -> Result<String, anyhow::Error> {
+        self.0
+            .render(name, context)
+            .with_context(|| format!("Failed to render the {} template", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use claim::{assert_err, assert_ok};
+    use tempfile::TempDir;
+    use crate::templates::{Templates, REQUIRED_TEMPLATES};
+
+    /// A copy of the `templates` directory to be broken by the test, removed once dropped
+    fn copy_templates() -> TempDir {
+        let directory = TempDir::new().unwrap();
+        for name in REQUIRED_TEMPLATES.iter().chain(["admin/base.html", "emails/base.html"].iter()) {
+            let target = directory.path().join(name);
+            std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+            std::fs::copy(PathBuf::from("templates").join(name), target).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn test_the_templates_of_the_repository_are_valid() {
+        assert_ok!(Templates::load());
+    }
+
+    #[test]
+    fn test_a_syntax_error_is_rejected() {
+        let directory = copy_templates();
+        std::fs::write(directory.path().join("emails/confirmation.txt"), "{{ name ").unwrap();
+        assert_err!(Templates::from_directory(directory.path()));
+    }
+
+    #[test]
+    fn test_a_missing_parent_template_is_rejected() {
+        let directory = copy_templates();
+        std::fs::remove_file(directory.path().join("admin/base.html")).unwrap();
+        assert_err!(Templates::from_directory(directory.path()));
+    }
+
+    #[test]
+    fn test_a_missing_template_is_rejected() {
+        let directory = copy_templates();
+        std::fs::remove_file(directory.path().join("emails/newsletter.txt")).unwrap();
+        assert_err!(Templates::from_directory(directory.path()));
+    }
+
+    #[test]
+    fn test_html_templates_are_escaped_but_not_text_ones() {
+        let templates = Templates::load().unwrap();
+        let mut context = tera::Context::new();
+        context.insert("name", "<Tom & Jerry>");
+        context.insert("confirmation_link", "https://example.com/confirm");
+        let html = templates.render("emails/confirmation.html", &context).unwrap();
+        assert!(html.contains("&lt;Tom &amp; Jerry&gt;"));
+        assert!(html.contains(r#"href="https://example.com/confirm""#));
+        let text = templates.render("emails/confirmation.txt", &context).unwrap();
+        assert!(text.contains("<Tom & Jerry>"));
+    }
+}
@@ -1,5 +1,7 @@
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{ContentType, LOCATION};
 use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+use crate::templates::Templates;
 
 /// Function to return an opaque 500 while logging the cause
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -13,4 +15,26 @@ pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
-}
\ No newline at end of file
+}
+/// Function to return a 400 with the user-facing error message
+pub fn e400<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static
+{
+    actix_web::error::ErrorBadRequest(e)
+}
+
+/// Render an HTML page, an opaque 500 when the template fails to render
+pub fn render_page(
+    templates: &Templates,
+    name: &str,
+    context: &tera::Context
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = templates.render(name, context).map_err(e500)?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(body))
+}
+
+/// Contents of the flash messages, as the `messages` of the admin layout
+pub fn flash_messages_content(flash_messages: &IncomingFlashMessages) -> Vec<String> {
+    flash_messages.iter().map(|m| m.content().to_owned()).collect()
+}
@@ -1,13 +1,16 @@
-use std::net::TcpListener;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use argon2::password_hash::SaltString;
 use once_cell::sync::Lazy;
 use uuid::Uuid;
-use email_newsletter_rust::configuration::{get_configuration, DatabaseSettings, Settings};
+use email_newsletter_rust::configuration::{get_configuration, DatabaseSettings};
 use email_newsletter_rust::email_client::EmailClient;
+use email_newsletter_rust::issue_scheduler::enqueue_due_issues;
+use email_newsletter_rust::issue_delivery_worker::{try_execute_task, ExecutionOutcome, RetryPolicy};
+use email_newsletter_rust::templates::Templates;
 use email_newsletter_rust::telemetry::{get_subscriber, init_subscriber};
 use sqlx::{PgConnection, Connection, PgPool, Executor};
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use email_newsletter_rust::startup::{get_connection_pool, Application};
 
 // Ensure that the `tracing` stack is only initialized once rather than for each test case
@@ -36,11 +39,14 @@ pub struct ConfirmationLink {
 pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
-    pub configuration: Settings,
     pub email_server: MockServer,
     pub port: u16,
     pub test_user: TestUser,
-    pub api_client: reqwest::Client
+    pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub templates: Templates,
+    pub subscription_token_ttl: chrono::Duration,
+    pub retry_policy: RetryPolicy
 }
 
 impl TestApp {
@@ -90,9 +96,373 @@ impl TestApp {
             .expect("Failed to execute POST request for Change Password")
     }
 
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries/failed", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Failed Deliveries")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_failed_deliveries()
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_requeue_failed_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/deliveries/failed/requeue", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Re-queue Failed Delivery")
+    }
+
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Publish Newsletter")
+    }
+
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter()
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Publish Newsletter")
+    }
+
+    pub async fn get_issues(&self, page: Option<u32>) -> reqwest::Response {
+        let mut url = format!("{}/admin/issues", &self.address);
+        if let Some(page) = page {
+            url.push_str(&format!("?page={}", page));
+        }
+        self.api_client
+            .get(url)
+            .send()
+            .await
+            .expect("Failed to execute GET request for Newsletter Issues")
+    }
+
+    pub async fn get_issues_html(&self, page: Option<u32>) -> String {
+        self.get_issues(page).await.text().await.unwrap()
+    }
+
+    pub async fn get_public_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for the Public Archive")
+    }
+
+    pub async fn get_public_issue(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute GET request for a Public Issue")
+    }
+
+    pub async fn get_issue_report(&self, newsletter_issue_id: &Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, newsletter_issue_id))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Issue Report")
+    }
+
+    pub async fn get_draft_form_html(&self, draft_id: &Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters?draft_id={}", &self.address, draft_id))
+            .send()
+            .await
+            .expect("Failed to execute GET request for the Draft Form")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issue_preview(&self, newsletter_issue_id: &Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}/preview", &self.address, newsletter_issue_id))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Issue Preview")
+    }
+
+    pub async fn get_issue_preview_html(&self, newsletter_issue_id: &Uuid) -> String {
+        self.get_issue_preview(newsletter_issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_send_test_issue<Body>(
+        &self,
+        newsletter_issue_id: &Uuid,
+        body: &Body
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/test", &self.address, newsletter_issue_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Send Test Issue")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Lists")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.get_lists().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_list<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Create List")
+    }
+
+    pub async fn post_rename_list<Body>(&self, list_id: &Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists/{}/rename", &self.address, list_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Rename List")
+    }
+
+    pub async fn post_archive_list(&self, list_id: &Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists/{}/archive", &self.address, list_id))
+            .send()
+            .await
+            .expect("Failed to execute POST request for Archive List")
+    }
+
+    pub async fn get_segments(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Segments")
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.get_segments().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_segment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Create Segment")
+    }
+
+    pub async fn post_preview_segment(&self, filter: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/segments/preview", &self.address))
+            .body(filter.to_string())
+            .send()
+            .await
+            .expect("Failed to execute POST request for Preview Segment")
+    }
+
+    pub async fn get_subscriber_attributes(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/attributes", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute GET request for Subscriber Attributes")
+    }
+
+    pub async fn get_subscriber_attributes_html(&self, email: &str) -> String {
+        self.get_subscriber_attributes(email).await.text().await.unwrap()
+    }
+
+    pub async fn post_subscriber_attributes<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers/attributes", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Subscriber Attributes")
+    }
+
+    /// `path_and_query` as found in the links of the page, e.g. `/admin/subscribers?status=confirmed`
+    pub async fn get_admin_page(&self, path_and_query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path_and_query))
+            .send()
+            .await
+            .expect("Failed to execute GET request for an admin page")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_admin_page(&format!("/admin/subscribers?{}", query)).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber(&self, subscriber_id: &Uuid) -> reqwest::Response {
+        self.get_admin_page(&format!("/admin/subscribers/{}", subscriber_id)).await
+    }
+
+    pub async fn get_subscriber_html(&self, subscriber_id: &Uuid) -> String {
+        self.get_subscriber(subscriber_id).await.text().await.unwrap()
+    }
+
+    /// One of the actions of the subscriber detail page, e.g. `confirm`
+    pub async fn post_subscriber_action(&self, subscriber_id: &Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/{}/{}", &self.address, subscriber_id, action))
+            .send()
+            .await
+            .expect("Failed to execute POST request for a Subscriber Action")
+    }
+
+    pub async fn post_rename_subscriber<Body>(&self, subscriber_id: &Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers/{}/rename", &self.address, subscriber_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Rename Subscriber")
+    }
+
+    /// Upload `csv` as `subscribers.csv` to the default list, `mode` is `confirmed` or `send_confirmation`
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let file = reqwest::multipart::Part::text(csv.to_owned())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap();
+        let form = reqwest::multipart::Form::new()
+            .text("mode", mode.to_owned())
+            .part("file", file);
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Import Subscribers")
+    }
+
+    pub async fn get_import_subscribers_html(&self) -> String {
+        self.get_admin_page("/admin/subscribers/import").await.text().await.unwrap()
+    }
+
+    /// `POST /subscribers/attributes`, authenticated as the test user
+    pub async fn post_subscriber_attributes_api(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscribers/attributes", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Subscriber Attributes API")
+    }
+
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute GET request for Preferences")
+    }
+
+    pub async fn get_preferences_html(&self, token: &str) -> String {
+        self.get_preferences(token).await.text().await.unwrap()
+    }
+
+    pub async fn post_preferences<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Preferences")
+    }
+
+    pub async fn get_scheduled_newsletters(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/scheduled", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute GET request for Scheduled Issues")
+    }
+
+    pub async fn get_scheduled_newsletters_html(&self) -> String {
+        self.get_scheduled_newsletters()
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_cancel_scheduled_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/scheduled/cancel", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST request for Cancel Scheduled Issue")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute POST request for Logout")
@@ -100,13 +470,38 @@ impl TestApp {
 }
 
 impl TestApp {
+    /// Run the `issue_delivery_worker` until the delivery queue is empty
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(
+                    &self.db_pool,
+                    &self.email_client,
+                    &self.templates,
+                    &self.retry_policy,
+                    &self.address
+                )
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Run the `issue_scheduler` once, then deliver whatever it enqueued
+    pub async fn dispatch_due_scheduled_issues(&self) {
+        enqueue_due_issues(&self.db_pool).await.unwrap();
+        self.dispatch_all_pending_emails().await;
+    }
+
     /// Create a new subscriber by sending a POST request to the `/subscriptions` endpoint
     pub async fn post_subscriptions(
         &self,
         body: String
     ) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -133,18 +528,32 @@ impl TestApp {
             confirmation_link
         };
 
-        let link = get_link(&body["text"].as_str().unwrap());
+        let html_link = get_link(body["html"].as_str().unwrap());
+        let text_link = get_link(body["text"].as_str().unwrap());
+        // Both parts of the email must point to the same confirmation link
+        assert_eq!(html_link, text_link);
         ConfirmationLink {
-            link
+            link: html_link
         }
     }
 
+    /// Extract the unsubscribe url from the `List-Unsubscribe` header of a newsletter email
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["headers"]["List-Unsubscribe"].as_str().unwrap();
+        let raw_link = header
+            .strip_prefix('<')
+            .and_then(|h| h.strip_suffix('>'))
+            .unwrap();
+        reqwest::Url::parse(raw_link).unwrap()
+    }
+
     pub async fn post_newsletters(
         &self,
         body: serde_json::Value
     ) -> reqwest::Response{
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             // Providing Generated Credentials here
             // `reqwest` handles all the encoding/formatting
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
@@ -154,6 +563,21 @@ impl TestApp {
             .expect("Failed to trigger newsletter request.")
     }
 
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to trigger newsletter request.")
+    }
+
 
     /// An utility function to test the login API
     ///
@@ -169,7 +593,7 @@ impl TestApp {
         Body: serde::Serialize
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This `reqwest` method is required to make sure that the body is URL-encoded
             // and the Content-type is set accordingly
             .form(body)
@@ -182,7 +606,7 @@ impl TestApp {
     /// rather than a complete `Response` object
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute GET Login request")
@@ -190,17 +614,52 @@ impl TestApp {
             .await
             .unwrap()
     }
+}
 
-    pub async fn test_user(&self) -> (String, String) {
-        let row = sqlx::query!(
-            r#"SELECT username, password_hash FROM users LIMIT 1"#
-        )
-            .fetch_one(&self.db_pool)
-            .await
-            .expect("Failed to create and fetch test users");
+/// Use the Public API of the application to create an unconfirmed subscriber
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+
+    // Test the subscription endpoint by sending a POST request
+    // This is required since the subscribe endpoint is updated to send a confirmation email
+    // Scoped mock: it is dropped at the end of the function and does not interfere
+    // with the expectations set by the caller on the newsletter emails
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // inspect the requests received by the mock server MailTrap server
+    // to retrieve the confirmation link and return it
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_link(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    // Reuse the same helper function and add and extra step to
+    // actually call the confirmation link
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
 
-        (row.username, row.password_hash)
-    }
 }
 
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
@@ -224,6 +683,14 @@ impl TestUser {
         }
     }
 
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+            .await;
+    }
+
     async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
 
@@ -302,17 +769,20 @@ pub async fn spawn_app() -> TestApp {
         "http://127.0.0.1:{}"
         ,application_port
     );
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     // Get the address of the server
     let test_app = TestApp {
         address,
         db_pool: get_connection_pool(&configuration.database),
-        configuration,
         email_server,
         port: application_port,
         test_user: TestUser::generate(),
-        api_client
+        api_client,
+        retry_policy: configuration.email_client.retry_policy().expect("Invalid retry policy"),
+        subscription_token_ttl: configuration.application.subscription_token_ttl(),
+        email_client: configuration.email_client.client(),
+        templates: Templates::load().expect("Failed to load the templates")
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -5,4 +5,22 @@ mod test_subscriptions_confirm;
 mod test_newsletter;
 mod test_login;
 mod test_admin_dashboard;
-mod test_change_password;
\ No newline at end of file
+mod test_change_password;
+mod test_dead_letters;
+mod test_subscriptions_unsubscribe;
+mod test_subscription_cleanup;
+mod test_scheduled_newsletters;
+mod test_admin_newsletters;
+mod test_issue_report;
+mod test_issue_archive;
+mod test_public_issues;
+mod test_feeds;
+mod test_issue_drafts;
+mod test_merge_tags;
+mod test_lists;
+mod test_subscriptions_preferences;
+mod test_segments;
+mod test_subscriber_attributes;
+mod test_admin_subscribers;
+mod test_admin_subscriber_actions;
+mod test_admin_subscriber_import;
@@ -1,4 +1,3 @@
-use email_newsletter_rust::routes::login;
 use crate::helpers::{assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
@@ -0,0 +1,129 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app};
+
+fn newsletter_form_body() -> serde_json::Value {
+    serde_json::json!({
+        "subject": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+        "category": "subscribers",
+        "send_at": "",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_see_the_newsletter_form() {
+    let app = spawn_app().await;
+
+    let response = app.get_publish_newsletter().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_publish_a_newsletter() {
+    let app = spawn_app().await;
+
+    let response = app.post_publish_newsletter(&newsletter_form_body()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_newsletters_published_from_the_form_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_publish_newsletter(&newsletter_form_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been published!"));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_newsletter_form_submission_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Submit the same form twice
+    let newsletter_request_body = newsletter_form_body();
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been published!"));
+
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been published!"));
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_a_newsletter_without_content_is_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_form_body();
+    body["html_content"] = "".into();
+    body["text_content"] = " ".into();
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Either an html or a text content must be provided"));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_newsletters_can_be_scheduled_from_the_form() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_form_body();
+    let send_at = chrono::Utc::now() + chrono::Duration::days(1);
+    body["send_at"] = send_at.format("%Y-%m-%dT%H:%M").to_string().into();
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    app.dispatch_all_pending_emails().await;
+}
@@ -0,0 +1,264 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp};
+
+struct AuditEntry {
+    user_id: Uuid,
+    action: String,
+    details: Option<String>,
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn subscriber_status(app: &TestApp) -> (String, String) {
+    let saved = sqlx::query!(
+        r#"
+            SELECT s.status AS "status!", m.status AS "membership_status!"
+            FROM subscriptions s
+            JOIN list_memberships m ON m.subscription_id = s.id
+        "#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (saved.status, saved.membership_status)
+}
+
+async fn audit_entries(app: &TestApp) -> Vec<AuditEntry> {
+    sqlx::query_as!(
+        AuditEntry,
+        "SELECT user_id, action, details FROM subscriber_audit_log ORDER BY created_at"
+    )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_act_on_subscribers() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    for action in ["confirm", "unsubscribe", "resend_confirmation", "delete"] {
+        let response = app.post_subscriber_action(&subscriber_id, action).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    let response = app.post_rename_subscriber(&subscriber_id, &serde_json::json!({"name": "ursula"})).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(audit_entries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_admins_can_confirm_pending_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+
+    let response = app.post_subscriber_action(&subscriber_id, "confirm").await;
+
+    assert_is_redirect_to(&response, &subscriber_page);
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("The subscriber has been confirmed."));
+    assert!(html_page.contains("<td>confirm</td>"));
+    assert_eq!(subscriber_status(&app).await, ("confirmed".into(), "confirmed".into()));
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "confirm");
+    assert_eq!(entries[0].user_id, app.test_user.user_id);
+    // The link sent to the subscriber has been used up
+    let response = reqwest::get(confirmation_link.link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+
+    app.post_subscriber_action(&subscriber_id, "confirm").await;
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("Only the subscribers pending confirmation can be confirmed."));
+    assert_eq!(audit_entries(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_admins_can_unsubscribe_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "unsubscribe").await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(subscriber_status(&app).await, ("unsubscribed".into(), "unsubscribed".into()));
+    assert_eq!(audit_entries(&app).await[0].action, "unsubscribe");
+
+    app.post_subscriber_action(&subscriber_id, "unsubscribe").await;
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("The subscriber has already unsubscribed."));
+}
+
+#[tokio::test]
+async fn test_admins_can_resend_the_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "resend_confirmation").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("A new confirmation email has been sent."));
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries[0].action, "resend_confirmation");
+    assert_eq!(entries[0].details, None);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_link.link).await.unwrap().error_for_status().unwrap();
+    assert_eq!(subscriber_status(&app).await, ("confirmed".into(), "confirmed".into()));
+}
+
+#[tokio::test]
+async fn test_a_failed_resend_is_reported_with_a_flash_message() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "resend_confirmation").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("The confirmation email could not be sent, try again later."));
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries[0].action, "resend_confirmation");
+    assert_eq!(entries[0].details.as_deref(), Some("email not sent"));
+}
+
+#[tokio::test]
+async fn test_an_invalid_stored_name_does_not_break_the_resend() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    sqlx::query!("UPDATE subscriptions SET name = '<script>' WHERE id = $1", subscriber_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/api/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "resend_confirmation").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("The stored email or name of the subscriber is not valid"));
+    assert!(audit_entries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_confirmed_subscribers_are_not_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    Mock::given(path("/api/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriber_action(&subscriber_id, "resend_confirmation").await;
+
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("Only the subscribers pending confirmation can be sent a confirmation email."));
+    assert!(audit_entries(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_admins_can_rename_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    let response = app.post_rename_subscriber(&subscriber_id, &serde_json::json!({"name": "Ursula Le Guin"})).await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("The subscriber has been renamed to Ursula Le Guin."));
+    assert!(html_page.contains(r#"value="Ursula Le Guin""#));
+    let entries = audit_entries(&app).await;
+    assert_eq!(entries[0].action, "rename");
+    assert_eq!(entries[0].details.as_deref(), Some("honda davidson -> Ursula Le Guin"));
+
+    app.post_rename_subscriber(&subscriber_id, &serde_json::json!({"name": "<script>"})).await;
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+    assert!(html_page.contains("&lt;script&gt; is not a valid subscriber name."));
+    assert_eq!(audit_entries(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_admins_can_delete_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("The subscriber honda_davidson@gmail.com has been deleted."));
+    let remaining = sqlx::query!(
+        r#"
+            SELECT
+                (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+                (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!",
+                (SELECT COUNT(*) FROM list_memberships) AS "memberships!"
+        "#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!((remaining.subscriptions, remaining.tokens, remaining.memberships), (0, 0, 0));
+    assert_eq!(audit_entries(&app).await[0].action, "delete");
+
+    let html_page = app.get_admin_page("/admin/subscribers/audit").await.text().await.unwrap();
+    assert!(html_page.contains("<td>honda_davidson@gmail.com</td>\n<td>delete</td>"));
+    let response = app.get_subscriber(&subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn test_actions_on_unknown_subscribers_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for action in ["confirm", "unsubscribe", "resend_confirmation", "delete"] {
+        let response = app.post_subscriber_action(&Uuid::new_v4(), action).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
@@ -0,0 +1,223 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+struct ImportedSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    membership_status: String,
+}
+
+/// The subscribers along with their membership of the default list, by email
+async fn subscribers(app: &TestApp) -> Vec<ImportedSubscriber> {
+    sqlx::query_as!(
+        ImportedSubscriber,
+        r#"
+            SELECT s.email AS "email!", s.name AS "name!", s.status AS "status!", m.status AS "membership_status!"
+            FROM subscriptions s
+            JOIN list_memberships m ON m.subscription_id = s.id
+            ORDER BY s.email
+        "#
+    )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+/// Wait for the confirmation emails of the latest import, sent in the background
+async fn wait_for_import_completion(app: &TestApp) {
+    for _ in 0..100 {
+        let import = sqlx::query!("SELECT completed_at FROM subscriber_imports ORDER BY created_at DESC LIMIT 1")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        if import.completed_at.is_some() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The import was not completed");
+}
+
+/// The results file of the latest import
+async fn import_results(app: &TestApp) -> String {
+    let import = sqlx::query!("SELECT import_id FROM subscriber_imports ORDER BY created_at DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .get_admin_page(&format!("/admin/subscribers/import/{}/results", import.import_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv; charset=utf-8");
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_page("/admin/subscribers/import").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_import_subscribers("email,name\nursula@example.com,ursula\n", "confirmed").await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(subscribers(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_imported_subscribers_can_be_marked_as_confirmed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "Name,company,Email\n\
+        Ursula Le Guin,Acme,ursula@example.com\n\
+        \"Le Guin, Ursula\",Acme,le-guin@example.com\n\
+        Ursula,Acme,not-an-email\n\
+        <script>,Acme,script@example.com\n\
+        Ursula again,Acme,ursula@example.com\n\
+        Honda,Acme,honda_davidson@gmail.com\n";
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("The import of subscribers.csv is done: 2 imported, 2 skipped, 2 rejected."));
+    assert!(html_page.contains("<td>subscribers.csv</td>"));
+    let imported: Vec<_> = subscribers(&app)
+        .await
+        .into_iter()
+        .filter(|s| s.email != "honda_davidson@gmail.com")
+        .map(|s| (s.email, s.name, s.status, s.membership_status))
+        .collect();
+    assert_eq!(
+        imported,
+        vec![
+            ("le-guin@example.com".into(), "Le Guin, Ursula".into(), "confirmed".into(), "confirmed".into()),
+            ("ursula@example.com".into(), "Ursula Le Guin".into(), "confirmed".into(), "confirmed".into()),
+        ]
+    );
+    assert_eq!(
+        import_results(&app).await,
+        "\"row\",\"email\",\"name\",\"result\",\"message\"\r\n\
+        \"1\",\"ursula@example.com\",\"Ursula Le Guin\",\"imported\",\"\"\r\n\
+        \"2\",\"le-guin@example.com\",\"Le Guin, Ursula\",\"imported\",\"\"\r\n\
+        \"3\",\"not-an-email\",\"Ursula\",\"rejected\",\"not-an-email is not a valid email address.\"\r\n\
+        \"4\",\"script@example.com\",\"<script>\",\"rejected\",\"<script> is not a valid subscriber name.\"\r\n\
+        \"5\",\"ursula@example.com\",\"Ursula again\",\"skipped\",\"Same address as row 1.\"\r\n\
+        \"6\",\"honda_davidson@gmail.com\",\"Honda\",\"skipped\",\"Already a subscriber, with the status confirmed.\"\r\n"
+    );
+    let audit = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscriber_audit_log WHERE action = 'import' AND details = 'confirmed'"#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.count, 2);
+}
+
+#[tokio::test]
+async fn test_imported_subscribers_can_be_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name\nursula@example.com,Ursula\nle-guin@example.com,Le Guin\n";
+    app.post_import_subscribers(csv, "send_confirmation").await;
+
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains(
+        "The import of subscribers.csv is done: 2 imported, 0 skipped, 0 rejected. \
+        The confirmation emails are being sent."
+    ));
+    wait_for_import_completion(&app).await;
+    assert!(!app.get_import_subscribers_html().await.contains("(sending the confirmation emails)"));
+    let statuses: Vec<_> = subscribers(&app).await.into_iter().map(|s| s.status).collect();
+    assert_eq!(statuses, vec!["pending_confirmation", "pending_confirmation"]);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_link.link).await.unwrap().error_for_status().unwrap();
+    let confirmed = subscribers(&app)
+        .await
+        .into_iter()
+        .filter(|s| s.status == "confirmed" && s.membership_status == "confirmed")
+        .count();
+    assert_eq!(confirmed, 1);
+}
+
+#[tokio::test]
+async fn test_failed_confirmation_emails_are_reported_in_the_results() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/api/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula\n", "send_confirmation").await;
+    wait_for_import_completion(&app).await;
+
+    let results = import_results(&app).await;
+    assert!(results.contains(
+        "\"imported\",\"The confirmation email could not be sent, resend it from the subscriber page.\""
+    ));
+    assert_eq!(subscribers(&app).await[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn test_invalid_files_are_not_imported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let cases = [
+        ("email,first_name\nursula@example.com,Ursula\n", "confirmed", "The header row of the file has no name column."),
+        ("", "confirmed", "The file is empty."),
+        ("email,name\nursula@example.com,Ursula\n", "pending", "pending is not a valid import mode."),
+    ];
+    for (csv, mode, error) in cases {
+        let response = app.post_import_subscribers(csv, mode).await;
+        assert_is_redirect_to(&response, "/admin/subscribers/import");
+        let html_page = app.get_import_subscribers_html().await;
+        assert!(html_page.contains(error), "{} was not reported", error);
+    }
+
+    let rows = "ursula@example.com,Ursula\n".repeat(10_001);
+    app.post_import_subscribers(&format!("email,name\n{}", rows), "confirmed").await;
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("The file cannot have more than 10000 rows."));
+
+    assert!(subscribers(&app).await.is_empty());
+    let imports = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriber_imports"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(imports.count, 0);
+}
+
+#[tokio::test]
+async fn test_the_results_of_unknown_imports_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_admin_page(&format!("/admin/subscribers/import/{}/results", Uuid::new_v4()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
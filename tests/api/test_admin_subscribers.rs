@@ -0,0 +1,183 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp};
+
+/// `n` confirmed subscribers `user<i>@example.com`, subscribed `i` minutes ago
+async fn insert_subscribers(app: &TestApp, n: i32) {
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            SELECT
+                gen_random_uuid(),
+                'user' || i || '@example.com',
+                'user ' || i,
+                now() - i * interval '1 minute',
+                'confirmed',
+                md5(i::text)
+            FROM generate_series(1, $1) AS i
+        "#,
+        n
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Emails listed by the page, in order
+fn listed_emails(html_page: &str) -> Vec<&str> {
+    html_page
+        .split(r#"<td><a href="/admin/subscribers/"#)
+        .skip(1)
+        .map(|row| {
+            let start = row.find("\">").unwrap() + 2;
+            let end = row.find("</a>").unwrap();
+            &row[start..end]
+        })
+        .collect()
+}
+
+/// Target of the `Next` link, if any
+fn next_page_link(html_page: &str) -> Option<String> {
+    let end = html_page.find(r#"">Next</a>"#)?;
+    let start = html_page[..end].rfind(r#"href=""#)? + r#"href=""#.len();
+    Some(html_page[start..end].replace("&amp;", "&"))
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_browse_the_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_page("/admin/subscribers").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_subscriber(&Uuid::new_v4()).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_subscribers_are_paginated_by_subscription_date() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscribers(&app, 55).await;
+
+    let html_page = app.get_subscribers_html("").await;
+    let emails = listed_emails(&html_page);
+    assert_eq!(emails.len(), 50);
+    assert_eq!(emails[0], "user1@example.com");
+    assert_eq!(emails[49], "user50@example.com");
+
+    let next_page = next_page_link(&html_page).expect("There is no next page");
+    let html_page = app.get_admin_page(&next_page).await.text().await.unwrap();
+    let emails = listed_emails(&html_page);
+    assert_eq!(emails, vec![
+        "user51@example.com",
+        "user52@example.com",
+        "user53@example.com",
+        "user54@example.com",
+        "user55@example.com",
+    ]);
+    assert_eq!(next_page_link(&html_page), None);
+
+    let html_page = app.get_subscribers_html("order=oldest").await;
+    let emails = listed_emails(&html_page);
+    assert_eq!(emails[0], "user55@example.com");
+    let next_page = next_page_link(&html_page).unwrap();
+    assert!(next_page.contains("order=oldest"));
+    let html_page = app.get_admin_page(&next_page).await.text().await.unwrap();
+    assert_eq!(listed_emails(&html_page).last(), Some(&"user1@example.com"));
+}
+
+#[tokio::test]
+async fn test_subscribers_can_be_searched_and_filtered_by_status() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscribers(&app, 12).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let html_page = app.get_subscribers_html("q=USER1").await;
+    assert_eq!(
+        listed_emails(&html_page),
+        vec!["user1@example.com", "user10@example.com", "user11@example.com", "user12@example.com"]
+    );
+
+    let html_page = app.get_subscribers_html("q=honda%20davidson").await;
+    assert_eq!(listed_emails(&html_page), vec!["honda_davidson@gmail.com"]);
+
+    // `_` is matched literally
+    let html_page = app.get_subscribers_html("q=_").await;
+    assert_eq!(listed_emails(&html_page), vec!["honda_davidson@gmail.com"]);
+
+    let html_page = app.get_subscribers_html("status=pending_confirmation").await;
+    assert_eq!(listed_emails(&html_page), vec!["honda_davidson@gmail.com"]);
+
+    let html_page = app.get_subscribers_html("q=user&status=pending_confirmation").await;
+    assert!(listed_emails(&html_page).is_empty());
+    assert!(html_page.contains("No subscribers."));
+
+    let response = app.get_admin_page("/admin/subscribers?status=bounced").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.get_admin_page("/admin/subscribers?after=yesterday").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_the_detail_page_shows_the_tokens_and_the_deliveries() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app, "honda_davidson@gmail.com").await;
+    let subscriber = sqlx::query!(
+        r#"
+            SELECT s.unsubscribe_token AS "unsubscribe_token!", t.subscription_token AS "subscription_token!"
+            FROM subscriptions s
+            JOIN subscription_tokens t ON t.subscription_id = s.id
+        "#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_subscriber_html(&subscriber_id).await;
+
+    assert!(html_page.contains("<h1>honda_davidson@gmail.com</h1>"));
+    assert!(html_page.contains("Status: confirmed"));
+    assert!(html_page.contains(&format!("<code>{}</code>", subscriber.unsubscribe_token)));
+    assert!(html_page.contains(&format!("<code>{}</code>", subscriber.subscription_token)));
+    assert!(html_page.contains("<td>Newsletter</td>"));
+    assert!(html_page.contains(">Newsletter title</a></td>\n<td>sent</td>"));
+}
+
+#[tokio::test]
+async fn test_unknown_subscribers_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscriber(&Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
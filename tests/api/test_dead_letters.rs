@@ -0,0 +1,218 @@
+use std::path::Path;
+use email_newsletter_rust::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use email_newsletter_rust::templates::Templates;
+use tempfile::TempDir;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    })
+}
+
+/// Make the re-scheduled deliveries due right away instead of waiting for the backoff
+async fn expire_backoff(app: &TestApp) {
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+/// Exhaust all the delivery attempts of the enqueued tasks
+async fn dispatch_until_dead_lettered(app: &TestApp) {
+    for _ in 0..app.retry_policy.max_attempts {
+        app.dispatch_all_pending_emails().await;
+        expire_backoff(app).await;
+    }
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // The first attempt fails, the task is kept in the queue for later
+    app.dispatch_all_pending_emails().await;
+    let task = sqlx::query!("SELECT n_attempts, execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.execute_after > chrono::Utc::now());
+
+    expire_backoff(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+#[tokio::test]
+async fn test_deliveries_are_dead_lettered_after_max_attempts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(u64::from(app.retry_policy.max_attempts))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body()).await;
+    dispatch_until_dead_lettered(&app).await;
+
+    let dead_letter = sqlx::query!(
+        "SELECT subscriber_email, n_attempts FROM issue_delivery_dead_letters"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead letter");
+    assert_eq!(dead_letter.subscriber_email, "honda_davidson@gmail.com");
+    assert_eq!(dead_letter.n_attempts, app.retry_policy.max_attempts as i16);
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+fn copy_directory(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            copy_directory(&entry.path(), &to.join(entry.file_name()));
+        } else {
+            std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_deliveries_failing_to_render_are_dead_lettered_after_max_attempts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    // The text part of the newsletters refers to a variable that is never set
+    let directory = TempDir::new().unwrap();
+    copy_directory(Path::new("templates"), directory.path());
+    std::fs::write(directory.path().join("emails/newsletter.txt"), "{{ missing_variable }}").unwrap();
+    let templates = Templates::from_directory(directory.path()).unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body()).await;
+    for _ in 0..app.retry_policy.max_attempts {
+        // Re-scheduled after each attempt rather than dequeued again right away
+        let outcome = try_execute_task(
+            &app.db_pool,
+            &app.email_client,
+            &templates,
+            &app.retry_policy,
+            &app.address
+        )
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+        let outcome = try_execute_task(
+            &app.db_pool,
+            &app.email_client,
+            &templates,
+            &app.retry_policy,
+            &app.address
+        )
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ExecutionOutcome::EmptyQueue));
+        expire_backoff(&app).await;
+    }
+
+    let dead_letter = sqlx::query!("SELECT n_attempts, last_error FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead letter");
+    assert_eq!(dead_letter.n_attempts, app.retry_policy.max_attempts as i16);
+    assert_eq!(dead_letter.last_error, "Failed to render the emails/newsletter.txt template");
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_see_the_failed_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app.get_failed_deliveries().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_can_be_requeued_by_an_admin() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body()).await;
+    dispatch_until_dead_lettered(&app).await;
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("honda_davidson@gmail.com"));
+
+    let response = app
+        .post_requeue_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": "honda_davidson@gmail.com"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("The delivery to honda_davidson@gmail.com has been re-queued."));
+
+    let task = sqlx::query!("SELECT subscriber_email, n_attempts FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery was not re-queued");
+    assert_eq!(task.subscriber_email, "honda_davidson@gmail.com");
+    assert_eq!(task.n_attempts, 0);
+}
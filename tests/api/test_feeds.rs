@@ -0,0 +1,130 @@
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use sha2::{Digest, Sha256};
+use crate::helpers::{spawn_app, TestApp};
+
+async fn get_feed(app: &TestApp, feed: &str, headers: &[(reqwest::header::HeaderName, &str)]) -> reqwest::Response {
+    let mut request = app.api_client.get(format!("{}/{}", &app.address, feed));
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    request.send().await.expect("Failed to execute GET request for a Feed")
+}
+
+async fn publish_newsletter(app: &TestApp, subject: &str) {
+    app.post_newsletters(serde_json::json!({
+        "subject": subject,
+        "html": "<p>Newsletter <b>body</b> content</p>",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_the_rss_feed_lists_the_published_issues() {
+    let app = spawn_app().await;
+    publish_newsletter(&app, "Tom & Jerry").await;
+    app.post_newsletters(serde_json::json!({
+        "subject": "Scheduled issue",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "send_at": (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339()
+    })).await.error_for_status().unwrap();
+
+    let response = get_feed(&app, "feed.rss", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/rss+xml; charset=utf-8");
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Tom &amp; Jerry</title>"));
+    // The HTML content is escaped once to be embedded in the XML document
+    assert!(feed.contains("&lt;p&gt;Newsletter &lt;b&gt;body&lt;/b&gt; content&lt;/p&gt;"));
+    assert!(!feed.contains("Scheduled issue"));
+}
+
+#[tokio::test]
+async fn test_the_atom_feed_lists_the_published_issues() {
+    let app = spawn_app().await;
+    publish_newsletter(&app, "Tom & Jerry").await;
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+
+    let response = get_feed(&app, "feed.atom", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/atom+xml; charset=utf-8");
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Tom &amp; Jerry</title>"));
+    assert!(feed.contains(&format!("/issues/{}</id>", slug)));
+    assert!(feed.contains(r#"<content type="html">&lt;p&gt;Newsletter"#));
+}
+
+#[tokio::test]
+async fn test_a_matching_etag_gets_a_304() {
+    let app = spawn_app().await;
+    publish_newsletter(&app, "Issue #1").await;
+
+    for feed in ["feed.rss", "feed.atom"] {
+        let response = get_feed(&app, feed, &[]).await;
+        let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+        // A SHA-256 hash of the feed, the same whatever the toolchain the application is built with
+        let hash = Sha256::digest(response.text().await.unwrap().as_bytes());
+        let hash: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(etag, format!("\"{}\"", hash));
+
+        let response = get_feed(&app, feed, &[(IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status().as_u16(), 304);
+        assert!(response.text().await.unwrap().is_empty());
+
+        let response = get_feed(&app, feed, &[(IF_NONE_MATCH, r#""outdated""#)]).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn test_the_etag_changes_when_an_issue_is_published() {
+    let app = spawn_app().await;
+    publish_newsletter(&app, "Issue #1").await;
+    let response = get_feed(&app, "feed.rss", &[]).await;
+    let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+
+    publish_newsletter(&app, "Issue #2").await;
+
+    let response = get_feed(&app, "feed.rss", &[(IF_NONE_MATCH, &etag)]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Issue #2"));
+}
+
+#[tokio::test]
+async fn test_if_modified_since_is_honoured() {
+    let app = spawn_app().await;
+    publish_newsletter(&app, "Issue #1").await;
+    let response = get_feed(&app, "feed.rss", &[]).await;
+    let last_modified = response.headers()[LAST_MODIFIED].to_str().unwrap().to_owned();
+
+    let response = get_feed(&app, "feed.rss", &[(IF_MODIFIED_SINCE, &last_modified)]).await;
+    assert_eq!(response.status().as_u16(), 304);
+
+    let response = get_feed(
+        &app,
+        "feed.rss",
+        &[(IF_MODIFIED_SINCE, "Sat, 01 Jan 2000 00:00:00 GMT")]
+    ).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn test_an_empty_feed_is_still_valid() {
+    let app = spawn_app().await;
+
+    let response = get_feed(&app, "feed.atom", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get(LAST_MODIFIED).is_none());
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+}
@@ -7,7 +7,7 @@ async fn test_health_check() {
     let address = spawn_app().await.address;
 
     let client = reqwest::Client::new();
-    let response = client.get(&format!("{}/health_check", &address))
+    let response = client.get(format!("{}/health_check", &address))
         .send()
         .await
         .expect("Failed to execute request");
@@ -0,0 +1,67 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp, subject: &str) {
+    app.post_newsletters(serde_json::json!({
+        "subject": subject,
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_see_the_issue_archive() {
+    let app = spawn_app().await;
+
+    let response = app.get_issues(None).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_published_issues_are_archived_with_their_author() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    publish_newsletter(&app, "Newsletter title").await;
+
+    let issue = sqlx::query!(
+        "SELECT subject, text_content, category, status, author_id FROM newsletter_issues"
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.subject, "Newsletter title");
+    assert_eq!(issue.text_content, "Newsletter body content");
+    assert_eq!(issue.category, "subscribers");
+    assert_eq!(issue.status, "published");
+    assert_eq!(issue.author_id, Some(app.test_user.user_id));
+
+    let html_page = app.get_issues_html(None).await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn test_the_issue_archive_is_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for i in 0..21 {
+        publish_newsletter(&app, &format!("Issue #{:02}", i)).await;
+    }
+
+    // Most recent first
+    let html_page = app.get_issues_html(None).await;
+    assert!(html_page.contains("Issue #20"));
+    assert!(!html_page.contains("Issue #00"));
+    assert!(html_page.contains(r#"<a href="/admin/issues?page=2">Next</a>"#));
+
+    let html_page = app.get_issues_html(Some(2)).await;
+    assert!(html_page.contains("Issue #00"));
+    assert!(!html_page.contains("Issue #20"));
+    assert!(html_page.contains(r#"<a href="/admin/issues?page=1">Previous</a>"#));
+    assert!(!html_page.contains("Next"));
+}
@@ -0,0 +1,227 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+fn draft_form_body(draft_id: Option<Uuid>, action: &str) -> serde_json::Value {
+    serde_json::json!({
+        "subject": "Draft title",
+        "html_content": "<p>Draft body as HTML</p>",
+        "text_content": "",
+        "category": "subscribers",
+        "send_at": "",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "draft_id": draft_id.map(|id| id.to_string()).unwrap_or_default(),
+        "action": action
+    })
+}
+
+async fn save_draft(app: &TestApp) -> Uuid {
+    let response = app.post_publish_newsletter(&draft_form_body(None, "draft")).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues WHERE status = 'draft'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn test_drafts_are_not_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    save_draft(&app).await;
+
+    let html_page = app.get_issues_html(None).await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains("<td>draft</td>"));
+    let deliveries = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, 0);
+    app.dispatch_all_pending_emails().await;
+    // Not in the public archive either
+    let html_page = app.get_public_issues().await.text().await.unwrap();
+    assert!(!html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn test_a_draft_can_be_reopened_and_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = save_draft(&app).await;
+
+    let html_page = app.get_draft_form_html(&draft_id).await;
+    assert!(html_page.contains(r#"value="Draft title""#));
+    assert!(html_page.contains("&lt;p&gt;Draft body as HTML&lt;/p&gt;</textarea>"));
+    assert!(html_page.contains(&draft_id.to_string()));
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_publish_newsletter(&draft_form_body(Some(draft_id), "publish")).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Same issue, no copy of the draft is left behind
+    let issue = sqlx::query!("SELECT newsletter_issue_id, status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.newsletter_issue_id, draft_id);
+    assert_eq!(issue.status, "published");
+}
+
+#[tokio::test]
+async fn test_a_published_issue_cannot_be_edited_as_a_draft() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = save_draft(&app).await;
+    app.post_publish_newsletter(&draft_form_body(Some(draft_id), "publish")).await;
+
+    let response = app.post_publish_newsletter(&draft_form_body(Some(draft_id), "draft")).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html(None).await;
+    assert!(html_page.contains("This issue is not a draft anymore."));
+
+    let html_page = app.get_draft_form_html(&draft_id).await;
+    assert!(!html_page.contains("Draft"));
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_preview_an_issue() {
+    let app = spawn_app().await;
+
+    let response = app.get_issue_preview(&Uuid::new_v4()).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_send_test_issue(
+        &Uuid::new_v4(),
+        &serde_json::json!({"recipients": "test@example.com"})
+    ).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_the_preview_renders_the_final_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = save_draft(&app).await;
+
+    let html_page = app.get_issue_preview_html(&draft_id).await;
+
+    assert!(html_page.contains("<h1>Draft title</h1>"));
+    // Along with the "View in browser" link added when sending
+    assert!(html_page.contains("View in browser&lt;/a&gt;&lt;/p&gt;\n&lt;p&gt;Draft body as HTML&lt;/p&gt;"));
+    assert!(html_page.contains("\n\nDraft body as HTML\n\nManage your preferences: "));
+    // And the footer links, to placeholder pages
+    assert!(html_page.contains(
+        "&lt;a href=&quot;http://127.0.0.1/subscriptions/unsubscribe&quot;&gt;Unsubscribe&lt;/a&gt;"
+    ));
+    assert!(html_page.contains("Unsubscribe: http://127.0.0.1/subscriptions/unsubscribe\n</pre>"));
+    assert!(html_page.contains(&format!("/admin/newsletters?draft_id={}", draft_id)));
+
+    let response = app.get_issue_preview(&Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn test_the_scripts_of_the_previewed_issue_cannot_run_in_the_admin_pages() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = draft_form_body(None, "draft");
+    body["html_content"] = "<script>fetch('/admin/password')</script>".into();
+    app.post_publish_newsletter(&body).await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let html_page = app.get_issue_preview_html(&draft_id).await;
+
+    assert!(html_page.contains(r#"<iframe title="HTML part" width="800" height="600" sandbox="allow-popups" srcdoc=""#));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn test_a_test_email_is_only_sent_to_the_entered_addresses() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = save_draft(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_send_test_issue(
+        &draft_id,
+        &serde_json::json!({"recipients": "first@example.com, second@example.com"})
+    ).await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", draft_id));
+    let html_page = app.get_issue_preview_html(&draft_id).await;
+    assert!(html_page.contains("A test email has been sent to 2 address(es)."));
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<String> = email_requests
+        .iter()
+        .skip(1) // The confirmation email sent by `create_confirmed_subscriber`
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            assert_eq!(body["subject"], "[TEST] Draft title");
+            body["to"][0]["email"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(recipients, vec!["first@example.com", "second@example.com"]);
+    // Still a draft, and nothing enqueued for the subscribers
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "draft");
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_invalid_test_addresses_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = save_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for (recipients, error) in [
+        ("first@example.com, not-an-email", "not-an-email is not a valid email address."),
+        ("", "At least one address is required."),
+    ] {
+        let response = app.post_send_test_issue(
+            &draft_id,
+            &serde_json::json!({"recipients": recipients})
+        ).await;
+        assert_is_redirect_to(&response, &format!("/admin/issues/{}/preview", draft_id));
+        let html_page = app.get_issue_preview_html(&draft_id).await;
+        assert!(html_page.contains(error), "Missing '{}'", error);
+    }
+}
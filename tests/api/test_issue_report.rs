@@ -0,0 +1,120 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_see_an_issue_report() {
+    let app = spawn_app().await;
+
+    let response = app.get_issue_report(&Uuid::new_v4()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_the_report_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_issue_report(&Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn test_deliveries_are_queued_on_publish() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    publish_newsletter(&app).await;
+
+    let delivery = sqlx::query!("SELECT subscriber_email, status FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.subscriber_email, "honda_davidson@gmail.com");
+    assert_eq!(delivery.status, "queued");
+}
+
+#[tokio::test]
+async fn test_sent_deliveries_record_the_provider_message_id() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true,
+            "message_ids": ["8b627ff0-52b2-11f0-0000-f1e8ba0efc25"]
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT status, provider_message_id FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.provider_message_id.as_deref(), Some("8b627ff0-52b2-11f0-0000-f1e8ba0efc25"));
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_issue_report(&newsletter_issue_id).await.text().await.unwrap();
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("<tr><td>sent</td><td>1</td></tr>"));
+    assert!(!html_page.contains("bounced"));
+    assert!(html_page.contains("No failed deliveries."));
+}
+
+#[tokio::test]
+async fn test_the_report_lists_the_failed_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    for _ in 0..app.retry_policy.max_attempts {
+        app.dispatch_all_pending_emails().await;
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+
+    let delivery = sqlx::query!("SELECT status, last_error FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert!(delivery.last_error.is_some());
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_issue_report(&newsletter_issue_id).await.text().await.unwrap();
+    assert!(html_page.contains("<tr><td>failed</td><td>1</td></tr>"));
+    assert!(html_page.contains("honda_davidson@gmail.com"));
+}
@@ -0,0 +1,278 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Create a list through the admin pages, returns its id
+async fn create_list(app: &TestApp, name: &str) -> Uuid {
+    let response = app.post_create_list(&serde_json::json!({"name": name})).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+/// Subscribe `email` to the list, then follow the confirmation link
+async fn subscribe_to_list(app: &TestApp, email: &str, list_id: &Uuid) {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=ursula&email={}&list={}", urlencoding::encode(email), list_id);
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_link.link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_to_list(app: &TestApp, list_id: Option<&Uuid>) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "list": list_id
+    })).await
+}
+
+/// Recipients of the newsletter emails, the confirmation emails left out
+async fn newsletter_recipients(app: &TestApp) -> Vec<String> {
+    let mut recipients: Vec<String> = app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["subject"] == "Newsletter title")
+        .map(|body| body["to"][0]["email"].as_str().unwrap().to_owned())
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app.get_lists().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_create_list(&serde_json::json!({"name": "Weekly digest"})).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_archive_list(&Uuid::new_v4()).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_lists_can_be_created_renamed_and_archived() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let list_id = create_list(&app, "Weekly digest").await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The list Weekly digest has been created."));
+    assert!(html_page.contains("<td>Newsletter (default)</td>"));
+    assert!(html_page.contains("<td>Weekly digest</td>"));
+
+    app.post_create_list(&serde_json::json!({"name": " Weekly digest "})).await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("A list named Weekly digest already exists."));
+
+    let response = app.post_rename_list(&list_id, &serde_json::json!({"name": "Monthly digest"})).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The list has been renamed to Monthly digest."));
+    assert!(html_page.contains("<td>Monthly digest</td>"));
+
+    let response = app.post_rename_list(&list_id, &serde_json::json!({"name": "Newsletter"})).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("A list named Newsletter already exists."));
+
+    let response = app.post_archive_list(&list_id).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The list has been archived."));
+    assert!(html_page.contains("<td>archived</td>"));
+}
+
+#[tokio::test]
+async fn test_the_default_list_cannot_be_archived() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let default_list_id = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    app.post_archive_list(&default_list_id).await;
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The default list and the archived lists cannot be archived."));
+    assert!(!html_page.contains("<td>archived</td>"));
+}
+
+#[tokio::test]
+async fn test_issues_are_only_sent_to_the_members_of_their_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Member of the default list
+    create_confirmed_subscriber(&app).await;
+    let list_id = create_list(&app, "Weekly digest").await;
+    subscribe_to_list(&app, "ursula@example.com", &list_id).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    assert_eq!(publish_to_list(&app, Some(&list_id)).await.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(newsletter_recipients(&app).await, vec!["ursula@example.com"]);
+
+    assert_eq!(publish_to_list(&app, None).await.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(
+        newsletter_recipients(&app).await,
+        vec!["honda_davidson@gmail.com", "ursula@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn test_joining_another_list_must_be_confirmed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Weekly digest").await;
+    create_confirmed_subscriber(&app).await;
+
+    // Already confirmed, but not a member of the list yet: a confirmation email is sent
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=honda%20davidson&email=honda_davidson%40gmail.com&list={}", list_id);
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    drop(_mock_guard);
+
+    publish_to_list(&app, Some(&list_id)).await.error_for_status().unwrap();
+    let deliveries = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, 0);
+
+    reqwest::get(confirmation_link.link).await.unwrap().error_for_status().unwrap();
+    let membership = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1",
+        list_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "confirmed");
+}
+
+#[tokio::test]
+async fn test_unsubscribing_leaves_every_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Weekly digest").await;
+    subscribe_to_list(&app, "ursula@example.com", &list_id).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    app.api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.address,
+            unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let membership = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(membership.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn test_archived_and_unknown_lists_cannot_be_subscribed_to_or_published_to() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "Weekly digest").await;
+    app.post_archive_list(&list_id).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for list_id in [list_id, Uuid::new_v4()] {
+        let body = format!("name=ursula&email=ursula%40example.com&list={}", list_id);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 400);
+
+        let response = publish_to_list(&app, Some(&list_id)).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn test_the_compose_form_publishes_to_the_selected_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let list_id = create_list(&app, "Weekly digest").await;
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(r#"<option value="{}">Weekly digest</option>"#, list_id)));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_publish_newsletter(&serde_json::json!({
+        "subject": "Newsletter title",
+        "html_content": "<p>Newsletter body</p>",
+        "text_content": "",
+        "category": "subscribers",
+        "send_at": "",
+        "list_id": list_id.to_string(),
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT list_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.list_id, list_id);
+}
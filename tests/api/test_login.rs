@@ -1,6 +1,4 @@
-use std::{collections::HashSet, str::FromStr};
 
-use reqwest::header::HeaderValue;
 
 use crate::helpers::{assert_is_redirect_to, spawn_app};
 
@@ -0,0 +1,180 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+fn newsletter_with_merge_tags() -> serde_json::Value {
+    serde_json::json!({
+        "subject": "News for {{subscriber.name}}",
+        "html": r#"<p>Hi {{subscriber.name | default: "there"}}!</p><a href="{{unsubscribe_url}}">Leave</a>"#,
+        "text": r#"Hi {{subscriber.name | default: "there"}}! Sent to {{subscriber.email}}"#,
+        "category": "subscribers"
+    })
+}
+
+/// A second confirmed subscriber, with an empty name for the fallbacks to kick in
+async fn create_nameless_subscriber(app: &TestApp) {
+    let subscription_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES ($1, 'nameless@example.com', '', now(), 'confirmed', 'nameless-token')
+        "#,
+        subscription_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+            INSERT INTO list_memberships (list_id, subscription_id, status, created_at)
+            SELECT list_id, $1, 'confirmed', now() FROM lists WHERE is_default
+        "#,
+        subscription_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_merge_tags_are_resolved_for_each_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_nameless_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_with_merge_tags()).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    // The first request is the confirmation email sent by `create_confirmed_subscriber`
+    for email_request in email_requests.iter().skip(1) {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = body["html"].as_str().unwrap();
+        let text = body["text"].as_str().unwrap();
+        match body["to"][0]["email"].as_str().unwrap() {
+            "honda_davidson@gmail.com" => {
+                assert_eq!(body["subject"], "News for honda davidson");
+                assert!(html.contains("<p>Hi honda davidson!</p>"));
+                assert!(text.contains("Hi honda davidson! Sent to honda_davidson@gmail.com"));
+            },
+            "nameless@example.com" => {
+                assert_eq!(body["subject"], "News for ");
+                assert!(html.contains("<p>Hi there!</p>"));
+                assert!(html.contains(r#"/subscriptions/unsubscribe?unsubscribe_token=nameless-token">Leave</a>"#));
+                assert!(text.contains("Hi there! Sent to nameless@example.com"));
+            },
+            recipient => panic!("Unexpected recipient {}", recipient),
+        }
+        assert!(!html.contains("{{"));
+        assert!(!text.contains("{{"));
+    }
+}
+
+#[tokio::test]
+async fn test_the_web_version_uses_the_fallbacks() {
+    let app = spawn_app().await;
+
+    let response = app.post_newsletters(newsletter_with_merge_tags()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+    let html_page = app.get_public_issue(&slug).await.text().await.unwrap();
+    assert!(html_page.contains("<h1>News for </h1>"));
+    assert!(html_page.contains("<p>Hi there!</p>"));
+    assert!(!html_page.contains("{{"));
+}
+
+#[tokio::test]
+async fn test_unknown_merge_tags_are_rejected_at_publish() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "subject": "News for {{subscriber.nmae}}",
+                "text": "Newsletter body content",
+                "category": "subscribers"
+            }),
+            "unknown tag in the subject"
+        ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter title",
+                "html": "<p>Hi {{ first_name }}</p>",
+                "category": "subscribers"
+            }),
+            "unknown tag in the html content"
+        ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter title",
+                "markdown": "Hi {{subscriber.name | default there}}",
+                "category": "subscribers"
+            }),
+            "invalid fallback in the markdown content"
+        ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter title",
+                "text": "Hi {{subscriber.name",
+                "category": "subscribers"
+            }),
+            "unclosed tag in the text content"
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_newsletters(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}",
+            error_message
+        );
+    }
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn test_the_form_reports_unknown_merge_tags() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_publish_newsletter(&serde_json::json!({
+        "subject": "Newsletter title",
+        "html_content": "<p>Hi {{subscriber.nmae}}</p>",
+        "text_content": "",
+        "category": "subscribers",
+        "send_at": "",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("{{subscriber.nmae}} is not a known merge tag"));
+}
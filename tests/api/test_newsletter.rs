@@ -1,7 +1,7 @@
 use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
-use crate::helpers::{spawn_app, ConfirmationLink, TestApp};
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn test_newsletters_returns_400_for_invalid_data() {
@@ -21,6 +21,32 @@ async fn test_newsletters_returns_400_for_invalid_data() {
             }),
             "missing content"
         ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter subject",
+                "html": " ",
+                "text": "",
+                "category": "subscribers"
+            }),
+            "blank content"
+        ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter subject",
+                "markdown": " ",
+                "category": "subscribers"
+            }),
+            "blank markdown content"
+        ),
+        (
+            serde_json::json!({
+                "subject": "Newsletter subject",
+                "markdown": "Newsletter **body**",
+                "html": "<p>Newsletter body</p>",
+                "category": "subscribers"
+            }),
+            "markdown along with html content"
+        ),
         (
             serde_json::json!({
                 "subject": "Newsletter subject",
@@ -45,7 +71,7 @@ async fn test_newsletters_returns_400_for_invalid_data() {
 #[tokio::test]
 async fn test_newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let app = spawn_app().await;
-    create_confirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app).await;
 
     Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
@@ -62,9 +88,10 @@ async fn test_newsletters_are_not_delivered_to_unconfirmed_subscribers() {
 
     // Will return 404 if the endpoint is not added
     let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
 
     // `Mock` verifies on `Drop` whether the newsletter mail is sent
-    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -75,6 +102,7 @@ async fn test_newsletters_are_delivered_to_confirmed_subscribers() {
     Mock::given(path("/api/send"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
+        .expect(1)
         .mount(&app.email_server)
         .await;
 
@@ -84,8 +112,103 @@ async fn test_newsletters_are_delivered_to_confirmed_subscribers() {
         "category": "subscribers"
     });
     let response = app.post_newsletters(newsletter_request_body).await;
+    // The issue is only enqueued, the emails are sent by the background worker
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_a_plain_text_part_is_generated_for_html_only_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "html": "<h1>Issue #1</h1><p>Newsletter <b>body</b> content</p>",
+        "category": "subscribers"
+    })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    // The first request is the confirmation email sent by `create_confirmed_subscriber`
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    // Wrapped in the `emails/newsletter.html` template
+    let html = body["html"].as_str().unwrap();
+    assert!(html.contains("<h1>Issue #1</h1><p>Newsletter <b>body</b> content</p>"));
+    let text = body["text"].as_str().unwrap();
+    assert!(!text.contains('<'), "The text part contains markup: {}", text);
+    assert!(text.contains("Issue #1"));
+    assert!(text.contains("content"));
+}
+
+#[tokio::test]
+async fn test_markdown_newsletters_are_sent_as_html_and_text() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "markdown": "# Issue #1\n\nNewsletter **body** content<script>alert(1)</script>",
+        "category": "subscribers"
+    })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    // The first request is the confirmation email sent by `create_confirmed_subscriber`
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    let html = body["html"].as_str().unwrap();
+    assert!(html.contains("<h1>Issue #1</h1>"));
+    assert!(html.contains("Newsletter <strong>body</strong> content"));
+    assert!(!html.contains("<script>"));
+    let text = body["text"].as_str().unwrap();
+    assert!(!text.contains('<'), "The text part contains markup: {}", text);
+    assert!(text.contains("Issue #1"));
+}
+
+#[tokio::test]
+async fn test_text_only_newsletters_are_sent_without_an_html_part() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    assert_eq!(response.status().as_u16(), 200);
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    // The first request is the confirmation email sent by `create_confirmed_subscriber`
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["text"].as_str().unwrap().contains("\n\nNewsletter body content\n"));
+    assert!(body.get("html").is_none());
 }
 
 #[tokio::test]
@@ -93,7 +216,7 @@ async fn test_requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(&serde_json::json!({
             "subject": "Newsletter title",
             "text": "<p>Newsletter body</p>",
@@ -118,7 +241,7 @@ async fn test_non_existing_user_is_rejected() {
     let password = Uuid::new_v4().to_string();
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "subject": "Testing newsletter endpoint CPU intensive tasks handling",
@@ -147,7 +270,7 @@ async fn test_invalid_password_is_rejected() {
     assert_ne!(app.test_user.password, password);
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "subject": "Testing newsletter endpoint CPU intensive tasks handling",
@@ -166,44 +289,84 @@ async fn test_invalid_password_is_rejected() {
     );
 }
 
-/// Use the Public API of the application to create an unconfirmed subscriber
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLink {
-    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+#[tokio::test]
+async fn test_newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    // Test the subscription endpoint by sending a POST request
-    // This is required since the subscribe endpoint is updated to send a confirmation email
     Mock::given(path("/api/send"))
-        .   and(method("POST"))
+        .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
+        // the retried request must not send the issue a second time
+        .expect(1)
         .mount(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    // inspect the requests received by the mock server MailTrap server
-    // to retrieve the confirmation link and return it
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "<p>Newsletter body content</p>",
+        "category": "subscribers"
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Retry the same request, as a client would after a timeout
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
 
-    app.get_confirmation_link(&email_request)
+    app.dispatch_all_pending_emails().await;
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    // Reuse the same helper function and add and extra step to
-    // actually call the confirmation link
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
+#[tokio::test]
+async fn test_concurrent_newsletter_submission_is_handled_gracefully() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-}
\ No newline at end of file
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "<p>Newsletter body content</p>",
+        "category": "subscribers"
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response1 = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
+
+    // Only one issue must have been enqueued
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_newsletters_returns_400_for_an_invalid_idempotency_key() {
+    let app = spawn_app().await;
+
+    let newsletter_request_body = serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "<p>Newsletter body content</p>",
+        "category": "subscribers"
+    });
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &"a".repeat(50))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,115 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn get_slug(app: &TestApp, subject: &str) -> String {
+    sqlx::query!("SELECT slug FROM newsletter_issues WHERE subject = $1", subject)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug
+}
+
+#[tokio::test]
+async fn test_published_issues_are_listed_in_the_public_archive() {
+    let app = spawn_app().await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Published issue",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    })).await.error_for_status().unwrap();
+    app.post_newsletters(serde_json::json!({
+        "subject": "Scheduled issue",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "send_at": (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339()
+    })).await.error_for_status().unwrap();
+
+    // No login required
+    let response = app.get_public_issues().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let slug = get_slug(&app, "Published issue").await;
+    assert!(html_page.contains(&format!(r#"<a href="/issues/{}">Published issue</a>"#, slug)));
+    assert!(!html_page.contains("Scheduled issue"));
+}
+
+#[tokio::test]
+async fn test_the_permalink_renders_the_issue_content() {
+    let app = spawn_app().await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Issue #1",
+        "html": "<p>Newsletter <b>body</b> content</p>",
+        "category": "subscribers"
+    })).await.error_for_status().unwrap();
+
+    let slug = get_slug(&app, "Issue #1").await;
+    assert!(slug.starts_with("issue-1-"));
+    let response = app.get_public_issue(&slug).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>Newsletter <b>body</b> content</p>"));
+}
+
+#[tokio::test]
+async fn test_text_only_issues_are_escaped() {
+    let app = spawn_app().await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Issue #1",
+        "text": "1 < 2",
+        "category": "subscribers"
+    })).await.error_for_status().unwrap();
+
+    let slug = get_slug(&app, "Issue #1").await;
+    let html_page = app.get_public_issue(&slug).await.text().await.unwrap();
+    assert!(html_page.contains("<pre>1 &lt; 2</pre>"));
+}
+
+#[tokio::test]
+async fn test_scheduled_and_unknown_issues_are_not_found() {
+    let app = spawn_app().await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Scheduled issue",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "send_at": (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339()
+    })).await.error_for_status().unwrap();
+
+    let slug = get_slug(&app, "Scheduled issue").await;
+    assert_eq!(app.get_public_issue(&slug).await.status().as_u16(), 404);
+    assert_eq!(app.get_public_issue("unknown-issue").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn test_newsletter_emails_link_to_the_web_version() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Issue #1",
+        "html": "<p>Newsletter body content</p>",
+        "category": "subscribers"
+    })).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let slug = get_slug(&app, "Issue #1").await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    let web_version_url = format!("/issues/{}", slug);
+    let html = body["html"].as_str().unwrap();
+    assert!(html.contains(&format!(r#"{}">View in browser</a>"#, web_version_url)));
+    let text = body["text"].as_str().unwrap();
+    assert!(text.starts_with("View in browser: "));
+    assert!(text.lines().next().unwrap().ends_with(&web_version_url));
+}
@@ -0,0 +1,221 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+fn scheduled_request_body(send_at: chrono::DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "subject": "Scheduled issue",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "send_at": send_at.to_rfc3339()
+    })
+}
+
+async fn scheduled_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues WHERE status = 'scheduled'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+/// Pretend the `send_at` of every scheduled issue has passed
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_scheduled_issues_are_not_delivered_before_send_at() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(scheduled_request_body(Utc::now() + Duration::hours(12))).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_due_scheduled_issues().await;
+    scheduled_issue_id(&app).await;
+}
+
+#[tokio::test]
+async fn test_scheduled_issues_are_delivered_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(scheduled_request_body(Utc::now() + Duration::hours(12)))
+        .await
+        .error_for_status()
+        .unwrap();
+    make_scheduled_issues_due(&app).await;
+    app.dispatch_due_scheduled_issues().await;
+    // Published only once
+    app.dispatch_due_scheduled_issues().await;
+
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn test_an_issue_failing_to_publish_does_not_hold_back_the_other_due_issues() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let send_at = Utc::now() + Duration::hours(12);
+    app.post_newsletters(scheduled_request_body(send_at)).await.error_for_status().unwrap();
+    let failing_issue_id = scheduled_issue_id(&app).await;
+    app.post_newsletters(scheduled_request_body(send_at)).await.error_for_status().unwrap();
+    // A segment filter that cannot be parsed anymore
+    let segment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO segments (segment_id, name, filter, created_at) VALUES ($1, 'Broken', '{"type": "unknown"}', now())"#,
+        segment_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE newsletter_issues SET segment_id = $1 WHERE newsletter_issue_id = $2",
+        segment_id,
+        failing_issue_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    make_scheduled_issues_due(&app).await;
+
+    app.dispatch_due_scheduled_issues().await;
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id, status FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    for issue in issues {
+        let expected = if issue.newsletter_issue_id == failing_issue_id { "scheduled" } else { "published" };
+        assert_eq!(issue.status, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_a_send_at_in_the_past_publishes_right_away() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(scheduled_request_body(Utc::now() - Duration::minutes(5)))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_see_the_scheduled_issues() {
+    let app = spawn_app().await;
+
+    let response = app.get_scheduled_newsletters().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_scheduled_issues_can_be_cancelled_by_an_admin() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(scheduled_request_body(Utc::now() + Duration::hours(12)))
+        .await
+        .error_for_status()
+        .unwrap();
+    let newsletter_issue_id = scheduled_issue_id(&app).await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("Scheduled issue"));
+
+    let response = app
+        .post_cancel_scheduled_newsletter(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("The scheduled issue has been cancelled."));
+    assert!(html_page.contains("No scheduled issues."));
+
+    // A cancelled issue is never sent, even once due
+    make_scheduled_issues_due(&app).await;
+    app.dispatch_due_scheduled_issues().await;
+}
+
+#[tokio::test]
+async fn test_an_already_published_issue_cannot_be_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(scheduled_request_body(Utc::now() + Duration::hours(12)))
+        .await
+        .error_for_status()
+        .unwrap();
+    let newsletter_issue_id = scheduled_issue_id(&app).await;
+    make_scheduled_issues_due(&app).await;
+    app.dispatch_due_scheduled_issues().await;
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_cancel_scheduled_newsletter(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("it may have already been sent"));
+}
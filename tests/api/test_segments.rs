@@ -0,0 +1,212 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+const EDU_FILTER: &str = r#"{"type": "email_domain", "domain": "edu"}"#;
+
+/// Create a segment through the admin pages, returns its id
+async fn create_segment(app: &TestApp, name: &str, filter: &str) -> Uuid {
+    let response = app.post_create_segment(&serde_json::json!({
+        "name": name,
+        "filter": filter
+    })).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    sqlx::query!("SELECT segment_id FROM segments WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .segment_id
+}
+
+/// Subscribe `email` to the default list, then follow the confirmation link
+async fn create_confirmed_subscriber_with_email(app: &TestApp, email: &str) {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=ursula&email={}", urlencoding::encode(email));
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    reqwest::get(confirmation_link.link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_to_segment(app: &TestApp, segment_id: &Uuid) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers",
+        "segment_id": segment_id
+    })).await
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_manage_segments() {
+    let app = spawn_app().await;
+
+    let response = app.get_segments().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_create_segment(&serde_json::json!({
+        "name": "Students",
+        "filter": EDU_FILTER
+    })).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_preview_segment(&serde_json::json!({"type": "status", "status": "confirmed"})).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_segments_can_be_created() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with_email(&app, "ursula@mit.edu").await;
+
+    create_segment(&app, "Students", EDU_FILTER).await;
+
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("The segment Students has been created."));
+    assert!(html_page.contains("<td>Students</td>"));
+    assert!(html_page.contains("<td>1</td>"));
+
+    app.post_create_segment(&serde_json::json!({
+        "name": "Students",
+        "filter": EDU_FILTER
+    })).await;
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("A segment named Students already exists."));
+}
+
+#[tokio::test]
+async fn test_segment_names_longer_than_100_characters_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_create_segment(&serde_json::json!({
+        "name": "a".repeat(101),
+        "filter": EDU_FILTER
+    })).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("The segment name cannot be longer than 100 characters."));
+    let segments = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(segments.count, 0);
+}
+
+#[tokio::test]
+async fn test_invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_create_segment(&serde_json::json!({
+        "name": "Bounced",
+        "filter": r#"{"type": "status", "status": "bounced"}"#
+    })).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("bounced is not a valid status"));
+    let segments = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(segments.count, 0);
+
+    let response = app.post_preview_segment(&serde_json::json!({"type": "unknown"})).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_the_preview_counts_the_matching_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_with_email(&app, "ursula@mit.edu").await;
+
+    let response = app.post_preview_segment(&serde_json::json!({
+        "type": "any",
+        "filters": [
+            {"type": "email_domain", "domain": "edu"},
+            {"type": "subscribed_within_days", "days": 1}
+        ]
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let audience: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(audience, serde_json::json!({"subscribers": 2, "confirmed_subscribers": 2}));
+
+    let response = app.post_preview_segment(&serde_json::json!({
+        "type": "not",
+        "filter": {"type": "email_domain", "domain": "edu"}
+    })).await;
+    let audience: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(audience, serde_json::json!({"subscribers": 1, "confirmed_subscribers": 1}));
+}
+
+#[tokio::test]
+async fn test_issues_are_only_sent_to_the_subscribers_of_their_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_with_email(&app, "ursula@mit.edu").await;
+    let segment_id = create_segment(&app, "Students", EDU_FILTER).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    assert_eq!(publish_to_segment(&app, &segment_id).await.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert_eq!(body["subject"], "Newsletter title");
+    assert_eq!(body["to"][0]["email"], "ursula@mit.edu");
+    let issue = sqlx::query!("SELECT segment_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.segment_id, Some(segment_id));
+}
+
+#[tokio::test]
+async fn test_unknown_segments_cannot_be_published_to() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = publish_to_segment(&app, &Uuid::new_v4()).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_the_compose_form_offers_the_segments() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let segment_id = create_segment(&app, "Students", EDU_FILTER).await;
+
+    let html_page = app.get_publish_newsletter_html().await;
+
+    assert!(html_page.contains(r#"<option value="">Everyone on the list</option>"#));
+    assert!(html_page.contains(&format!(r#"<option value="{}">Students</option>"#, segment_id)));
+}
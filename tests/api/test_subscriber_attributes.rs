@@ -0,0 +1,239 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+const EMAIL: &str = "honda_davidson@gmail.com";
+
+struct StoredAttributes {
+    attributes: serde_json::Value,
+    tags: Vec<String>,
+}
+
+async fn stored_attributes(app: &TestApp) -> StoredAttributes {
+    sqlx::query_as!(StoredAttributes, "SELECT attributes, tags FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_the_subscribe_form_sets_the_allowed_attributes_and_tags() {
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=ursula&email=ursula%40example.com&attribute%3Asource=landing-page&tags=Beta";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let stored = stored_attributes(&app).await;
+    assert_eq!(stored.attributes, serde_json::json!({"source": "landing-page"}));
+    assert_eq!(stored.tags, vec!["beta"]);
+}
+
+#[tokio::test]
+async fn test_the_subscribe_form_rejects_other_attributes_and_tags() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for (body, error_message) in [
+        ("name=ursula&email=ursula%40example.com&attribute%3Aplan=enterprise", "unknown attribute"),
+        ("name=ursula&email=ursula%40example.com&tags=vip", "unknown tag"),
+        ("name=ursula&email=ursula%40example.com&tags=not%20a%20tag", "invalid tag"),
+    ] {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 400, "The API did not fail with 400 for {}", error_message);
+    }
+}
+
+#[tokio::test]
+async fn test_the_api_merges_attributes_and_tags() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_subscriber_attributes_api(serde_json::json!({
+        "email": EMAIL,
+        "attributes": {"company": "Acme", "seats": 12},
+        "add_tags": ["vip", "beta"]
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_subscriber_attributes_api(serde_json::json!({
+        "email": EMAIL,
+        "attributes": {"seats": null, "plan": "pro"},
+        "remove_tags": ["beta"]
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, serde_json::json!({
+        "email": EMAIL,
+        "attributes": {"company": "Acme", "plan": "pro"},
+        "tags": ["vip"]
+    }));
+    let stored = stored_attributes(&app).await;
+    assert_eq!(stored.attributes, serde_json::json!({"company": "Acme", "plan": "pro"}));
+    assert_eq!(stored.tags, vec!["vip"]);
+}
+
+#[tokio::test]
+async fn test_the_api_rejects_invalid_requests() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.api_client
+        .post(format!("{}/subscribers/attributes", &app.address))
+        .json(&serde_json::json!({"email": EMAIL, "add_tags": ["vip"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_subscriber_attributes_api(serde_json::json!({
+        "email": "ursula@example.com",
+        "add_tags": ["vip"]
+    })).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    for body in [
+        serde_json::json!({"email": EMAIL, "attributes": {"Company": "Acme"}}),
+        serde_json::json!({"email": EMAIL, "attributes": {"company": {"name": "Acme"}}}),
+        serde_json::json!({"email": EMAIL, "add_tags": ["not a tag"]}),
+    ] {
+        let response = app.post_subscriber_attributes_api(body.clone()).await;
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", body);
+    }
+}
+
+#[tokio::test]
+async fn test_you_must_be_logged_in_to_edit_the_attributes() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscriber_attributes(EMAIL).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_subscriber_attributes(&serde_json::json!({
+        "email": EMAIL,
+        "tags": "vip",
+        "attributes": ""
+    })).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn test_admins_can_edit_the_attributes_and_tags() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_subscriber_attributes(&serde_json::json!({
+        "email": EMAIL,
+        "tags": "vip, beta",
+        "attributes": r#"{"company": "Acme"}"#
+    })).await;
+    let attributes_page = "/admin/subscribers/attributes?email=honda_davidson%40gmail.com";
+    assert_is_redirect_to(&response, attributes_page);
+    let html_page = app.get_subscriber_attributes_html(EMAIL).await;
+    assert!(html_page.contains("The tags and attributes have been saved."));
+    assert!(html_page.contains(r#"value="beta, vip""#));
+    let stored = stored_attributes(&app).await;
+    assert_eq!(stored.attributes, serde_json::json!({"company": "Acme"}));
+    assert_eq!(stored.tags, vec!["beta", "vip"]);
+
+    let response = app.post_subscriber_attributes(&serde_json::json!({
+        "email": EMAIL,
+        "tags": "",
+        "attributes": "[1, 2]"
+    })).await;
+    assert_is_redirect_to(&response, attributes_page);
+    let html_page = app.get_subscriber_attributes_html(EMAIL).await;
+    assert!(html_page.contains("The attributes must be a JSON object"));
+    assert_eq!(stored_attributes(&app).await.tags, vec!["beta", "vip"]);
+
+    let html_page = app.get_subscriber_attributes_html("ursula@example.com").await;
+    assert!(html_page.contains("There is no subscriber with this address."));
+}
+
+#[tokio::test]
+async fn test_the_address_is_trimmed_when_saving_the_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    let html_page = app.get_subscriber_attributes_html(&format!(" {} ", EMAIL)).await;
+    assert!(html_page.contains(&format!(r#"value="{}""#, EMAIL)));
+    let response = app.post_subscriber_attributes(&serde_json::json!({
+        "email": format!(" {} ", EMAIL),
+        "tags": "vip",
+        "attributes": ""
+    })).await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers/attributes?email=honda_davidson%40gmail.com");
+    assert_eq!(stored_attributes(&app).await.tags, vec!["vip"]);
+}
+
+#[tokio::test]
+async fn test_attributes_are_merged_into_the_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_subscriber_attributes_api(serde_json::json!({
+        "email": EMAIL,
+        "attributes": {"company": "Acme"}
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "News for {{subscriber.attributes.company}}",
+        "text": r#"Your plan: {{subscriber.attributes.plan | default: "free"}}"#,
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert_eq!(body["subject"], "News for Acme");
+    assert!(body["text"].as_str().unwrap().contains("Your plan: free"));
+}
+
+#[tokio::test]
+async fn test_segments_can_match_tags_and_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    let filter = serde_json::json!({"type": "all", "filters": [
+        {"type": "tag", "tag": "vip"},
+        {"type": "attribute", "key": "company", "value": "Acme"}
+    ]});
+    let audience: serde_json::Value = app.post_preview_segment(&filter).await.json().await.unwrap();
+    assert_eq!(audience["subscribers"], 0);
+
+    app.post_subscriber_attributes_api(serde_json::json!({
+        "email": EMAIL,
+        "attributes": {"company": "Acme"},
+        "add_tags": ["vip"]
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let audience: serde_json::Value = app.post_preview_segment(&filter).await.json().await.unwrap();
+    assert_eq!(audience["subscribers"], 1);
+}
@@ -1,6 +1,6 @@
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn test_subscribe_returns_200_for_valid_form_data() {
@@ -43,7 +43,7 @@ async fn subscribe_persists_the_new_subscriber() {
 
     assert_eq!(saved.email, "honda_davidson@gmail.com");
     assert_eq!(saved.name, "honda davidson");
-    assert_eq!(saved.status, "pending_confirmation");;
+    assert_eq!(saved.status, "pending_confirmation");
 }
 
 #[tokio::test]
@@ -90,11 +90,174 @@ async fn test_subscribe_sends_a_confirmation_email_for_valid_data() {
         .received_requests()
         .await
         .unwrap()[0];
-    let confirmation_link = app.get_confirmation_link(&email_request).link;
+    let confirmation_link = app.get_confirmation_link(email_request).link;
 
     assert!(!confirmation_link.host_str().unwrap().is_empty());
 }
 
+#[tokio::test]
+async fn test_the_confirmation_email_greets_the_new_subscriber() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=honda%20davidson&email=honda_davidson%40gmail.com".into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["subject"], "Welcome!");
+    assert!(body["html"].as_str().unwrap().contains("Welcome to our newsletter, honda davidson!"));
+    assert!(body["text"].as_str().unwrap().contains("Welcome to our newsletter, honda davidson!"));
+}
+
+#[tokio::test]
+async fn test_subscribing_twice_while_pending_resends_a_new_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    assert_eq!(app.post_subscriptions(body.into()).await.status().as_u16(), 200);
+    assert_eq!(app.post_subscriptions(body.into()).await.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_link(&email_requests[0]).link;
+    let second_link = app.get_confirmation_link(&email_requests[1]).link;
+    assert_ne!(first_link, second_link);
+
+    // The existing row is reused
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+
+    // The latest link confirms the subscription
+    reqwest::get(second_link).await.unwrap().error_for_status().unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn test_concurrent_subscriptions_of_the_same_address_are_both_accepted() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count the subscriptions");
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn test_the_resent_confirmation_email_greets_the_subscriber_with_the_stored_name() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+    // Anyone can submit the form with the address of an existing subscriber
+    let body = "name=You%20won%20a%20prize&email=honda_davidson%40gmail.com";
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = email["text"].as_str().unwrap();
+    assert!(text.contains("Welcome to our newsletter, honda davidson!"));
+    assert!(!text.contains("You won a prize"));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.name, "honda davidson");
+}
+
+#[tokio::test]
+async fn test_subscribing_again_once_confirmed_returns_200_without_sending_an_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Same email address as `create_confirmed_subscriber`
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn test_unsubscribed_subscribers_must_confirm_again_when_subscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=honda%20davidson&email=honda_davidson%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
 /// # sqlx logs are a bit spammy, cutting them out to reduce noise
 /// export RUST_LOG="sqlx=error,info"<br/>
 /// export TEST_LOG=enabled<br/>
@@ -0,0 +1,79 @@
+use email_newsletter_rust::subscription_cleanup_worker::{purge_stale_subscriptions, PurgeOutcome};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Move every subscription and token past the token TTL
+async fn expire_everything(app: &TestApp) {
+    let expired_at = chrono::Utc::now() - app.subscription_token_ttl - chrono::Duration::minutes(1);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = $1", expired_at)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = $1", expired_at)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn insert_pending_subscriber(app: &TestApp, email: &str) {
+    app.post_subscriptions(format!("name=le%20guin&email={}", urlencoding::encode(email)))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_expired_tokens_and_never_confirmed_subscriptions_are_purged() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    insert_pending_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    expire_everything(&app).await;
+    // Subscribed recently, the confirmation link is still valid
+    insert_pending_subscriber(&app, "ursula_k_le_guin@gmail.com").await;
+
+    let outcome = purge_stale_subscriptions(&app.db_pool, app.subscription_token_ttl)
+        .await
+        .unwrap();
+
+    // The token consumed by the confirmed subscriber is kept
+    assert_eq!(outcome, PurgeOutcome { purged_tokens: 1, purged_subscriptions: 1 });
+    let remaining: Vec<_> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(remaining, vec!["honda_davidson@gmail.com", "ursula_k_le_guin@gmail.com"]);
+    let consumed_tokens = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE consumed_at IS NOT NULL"#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consumed_tokens.count, 1);
+}
+
+#[tokio::test]
+async fn test_a_pending_subscription_with_a_fresh_token_is_kept() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    insert_pending_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    expire_everything(&app).await;
+    // Subscribing again issues a new token for the same row
+    insert_pending_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    let outcome = purge_stale_subscriptions(&app.db_pool, app.subscription_token_ttl)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, PurgeOutcome { purged_tokens: 1, purged_subscriptions: 0 });
+}
@@ -1,6 +1,6 @@
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn test_confirmation_without_token_are_rejected_with_a_400() {
@@ -28,7 +28,7 @@ async fn test_the_link_returned_by_subscribe_returns_a_200_if_called() {
 
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let mut confirmation_link = app.get_confirmation_link(&email_request).link;
+    let mut confirmation_link = app.get_confirmation_link(email_request).link;
 
     assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
 
@@ -61,7 +61,7 @@ async fn test_clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await
         .unwrap()[0];
 
-    let confirmation_link = app.get_confirmation_link(&email_request);
+    let confirmation_link = app.get_confirmation_link(email_request);
 
     reqwest::get(confirmation_link.link)
         .await
@@ -80,4 +80,41 @@ async fn test_clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "honda davidson");
     assert_eq!(saved.status, "confirmed");
 
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_a_confirmation_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+
+    reqwest::get(confirmation_link.link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = reqwest::get(confirmation_link.link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("already been used"));
+}
+
+#[tokio::test]
+async fn test_an_expired_confirmation_link_is_rejected() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let expired_at = chrono::Utc::now() - app.subscription_token_ttl - chrono::Duration::minutes(1);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = $1", expired_at)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link.link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("expired"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
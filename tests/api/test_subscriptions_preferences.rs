@@ -0,0 +1,229 @@
+use chrono::{Duration, Utc};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn unsubscribe_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token
+}
+
+fn preferences_page(token: &str) -> String {
+    format!("/subscriptions/preferences?token={}", token)
+}
+
+/// Publish an issue of the given category, returns the number of deliveries it enqueued
+async fn publish_issue_of_category(app: &TestApp, category: &str) -> i64 {
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": category
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let deliveries = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!"
+            FROM issue_delivery_queue
+            JOIN newsletter_issues USING (newsletter_issue_id)
+            WHERE category = $1
+        "#,
+        category
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    deliveries.count
+}
+
+#[tokio::test]
+async fn test_an_unknown_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("unknown-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_preferences(&serde_json::json!({
+        "token": "unknown-token",
+        "name": "ursula"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn test_the_page_lists_the_categories_of_the_sent_issues() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+    publish_issue_of_category(&app, "events").await;
+    publish_issue_of_category(&app, "digest").await;
+
+    let html_page = app.get_preferences_html(&token).await;
+
+    assert!(html_page.contains(r#"value="honda davidson""#));
+    assert!(html_page.contains(r#"<input type="checkbox" name="category:digest" checked>"#));
+    assert!(html_page.contains(r#"<input type="checkbox" name="category:events" checked>"#));
+}
+
+#[tokio::test]
+async fn test_the_page_leaves_out_the_categories_of_unannounced_issues_and_of_other_lists() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+    app.test_user.login(&app).await;
+    app.post_create_list(&serde_json::json!({"name": "Staff"})).await;
+    let staff_list = sqlx::query!("SELECT list_id FROM lists WHERE name = 'Staff'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    publish_issue_of_category(&app, "digest").await;
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "launch",
+        "send_at": (Utc::now() + Duration::days(1)).to_rfc3339()
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "payroll",
+        "list": staff_list
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let html_page = app.get_preferences_html(&token).await;
+
+    assert!(html_page.contains(r#"name="category:digest""#));
+    assert!(!html_page.contains("launch"));
+    assert!(!html_page.contains("payroll"));
+}
+
+#[tokio::test]
+async fn test_subscribers_can_change_their_name() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+
+    let response = app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "Ursula Le Guin",
+        "pause_weeks": ""
+    })).await;
+    assert_is_redirect_to(&response, &preferences_page(&token));
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("Your preferences have been saved."));
+    assert!(html_page.contains(r#"value="Ursula Le Guin""#));
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "<script>"
+    })).await;
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("&lt;script&gt; is not a valid subscriber name."));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula Le Guin");
+}
+
+#[tokio::test]
+async fn test_opted_out_categories_are_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+    assert_eq!(publish_issue_of_category(&app, "events").await, 1);
+    assert_eq!(publish_issue_of_category(&app, "digest").await, 1);
+
+    // Only `digest` is still checked
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "honda davidson",
+        "category:digest": "on"
+    })).await;
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains(r#"<input type="checkbox" name="category:events">"#));
+    assert_eq!(publish_issue_of_category(&app, "events").await, 1);
+    assert_eq!(publish_issue_of_category(&app, "digest").await, 2);
+    // Categories of later issues are received by default
+    assert_eq!(publish_issue_of_category(&app, "announcements").await, 1);
+}
+
+#[tokio::test]
+async fn test_paused_subscribers_receive_nothing_until_they_resume() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "honda davidson",
+        "pause_weeks": "2"
+    })).await;
+    let saved = sqlx::query!("SELECT paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let paused_until = saved.paused_until.unwrap();
+    assert!(paused_until > Utc::now() + Duration::days(13));
+    assert!(paused_until < Utc::now() + Duration::days(15));
+    assert!(app.get_preferences_html(&token).await.contains("The deliveries are paused until"));
+    assert_eq!(publish_issue_of_category(&app, "events").await, 0);
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "honda davidson",
+        "pause_weeks": "60"
+    })).await;
+    assert!(app.get_preferences_html(&token).await.contains("The pause must be between 0 and 52 weeks."));
+
+    app.post_preferences(&serde_json::json!({
+        "token": token,
+        "name": "honda davidson",
+        "pause_weeks": "0"
+    })).await;
+    assert!(!app.get_preferences_html(&token).await.contains("The deliveries are paused until"));
+    assert_eq!(publish_issue_of_category(&app, "digest").await, 1);
+}
+
+#[tokio::test]
+async fn test_newsletters_link_to_the_preferences_page() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = unsubscribe_token(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Change your settings: {{preferences_url}}",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    let text = body["text"].as_str().unwrap();
+    let preferences_url = format!("{}{}", app.address, preferences_page(&token));
+    assert!(text.contains(&format!("Change your settings: {}", preferences_url)));
+    assert!(text.contains(&format!("Manage your preferences: {}", preferences_url)));
+}
@@ -0,0 +1,180 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Publish an issue to the confirmed subscriber and return the unsubscribe link it carries
+async fn unsubscribe_link_from_newsletter(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+/// What mail clients send for RFC 8058 one-click unsubscribe, and the confirmation form of the landing page
+async fn post_unsubscribe_link(app: &TestApp, unsubscribe_link: reqwest::Url) -> reqwest::Response {
+    app
+        .api_client
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch subscriber status")
+        .status
+}
+
+#[tokio::test]
+async fn test_unsubscribe_without_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_unsubscribe_with_an_unknown_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=unknown",
+        app.address
+    ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn test_newsletters_carry_the_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["headers"]["List-Unsubscribe-Post"], "List-Unsubscribe=One-Click");
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+}
+
+#[tokio::test]
+async fn test_newsletters_carry_the_unsubscribe_link_in_their_body() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["text"].as_str().unwrap();
+    assert!(text.contains(&format!("Unsubscribe: {}", unsubscribe_link)));
+}
+
+#[tokio::test]
+async fn test_the_unsubscribe_link_asks_for_a_confirmation_before_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+
+    // Mail scanners and link prefetchers follow the links of the emails
+    let response = reqwest::get(unsubscribe_link.clone()).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="{}?{}" method="post">"#,
+        unsubscribe_link.path(),
+        unsubscribe_link.query().unwrap()
+    )));
+    assert!(!html_page.contains("You have been unsubscribed"));
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+
+    let response = post_unsubscribe_link(&app, unsubscribe_link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("You have been unsubscribed"));
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn test_one_click_unsubscribe_unsubscribes_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+
+    let response = post_unsubscribe_link(&app, unsubscribe_link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn test_newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+    post_unsubscribe_link(&app, unsubscribe_link).await.error_for_status().unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn test_pending_deliveries_are_skipped_after_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = unsubscribe_link_from_newsletter(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // The issue is enqueued while the subscriber is still confirmed
+    let response = app.post_newsletters(serde_json::json!({
+        "subject": "Newsletter title",
+        "text": "Newsletter body content",
+        "category": "subscribers"
+    })).await;
+    assert_eq!(response.status().as_u16(), 202);
+    post_unsubscribe_link(&app, unsubscribe_link).await.error_for_status().unwrap();
+    app.dispatch_all_pending_emails().await;
+}